@@ -1,13 +1,17 @@
-use core::{fmt, num};
+use core::fmt;
 
 use crate::distribution::Distribution;
-use crate::utils::Rng;
+use crate::core::RngCore;
 
 #[cfg(feature="serde1")]
-use serde::{Serialize, Deserialize};
+use serde::Serialize;
+#[cfg(any(feature="serde1", feature="candid"))]
+use serde::Deserialize;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
-#[cfg_attr(feature="serde1", derive(Serialize, Deserialize))]
+#[cfg_attr(feature="serde1", derive(Serialize))]
+#[cfg_attr(any(feature="serde1", feature="candid"), derive(Deserialize))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
 pub struct Bernoulli {
     p_int: u64,
 }
@@ -60,19 +64,19 @@ impl Bernoulli {
             return Ok(Bernoulli { p_int: ALWAYS_TRUE });
         }
         let p_int = ((f64::from(numerator) / f64::from(denominator)) * SCALE) as u64;
-        Ok(Bernoulli { p_int: p_int })
+        Ok(Bernoulli { p_int })
     }
 }
 
 impl Distribution<bool> for Bernoulli {
     #[inline]
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> bool {
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> bool {
         //make sure to always return true for p = 1.0
         if self.p_int == ALWAYS_TRUE {
             return true;
         }
-        let v: u32 = rng.rand_u32();
-        v < self.p_int as u8 as u32
+        let v: u64 = rng.next_u64();
+        v < self.p_int
     }
 }
 
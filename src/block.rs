@@ -0,0 +1,534 @@
+//! Buffering for generators which produce a block of words at a time.
+//!
+//! Generators built on a block function (ChaCha, or a counter-mode cipher)
+//! only need to implement `BlockRngCore`; wrapping the core in `BlockRng`
+//! or `BlockRng64` turns its blocks into `RngCore` output, handling the
+//! buffer index, 64-bit values straddling two blocks, half-used words and
+//! bulk byte copies.
+
+use crate::core::{Error, RngCore, SeedableRng};
+
+#[cfg(feature = "serde1")]
+use serde::Serialize;
+#[cfg(any(feature = "serde1", feature = "candid"))]
+use serde::Deserialize;
+#[cfg(any(feature = "serde1", feature = "candid"))]
+use crate::stable::StateError;
+
+/// A generator producing a fixed-size block of words per step.
+pub trait BlockRngCore {
+    /// The word type, `u32` for `BlockRng` or `u64` for `BlockRng64`.
+    type Item;
+
+    /// The block, e.g. `[u32; 16]`.
+    type Results: AsRef<[Self::Item]> + AsMut<[Self::Item]> + Default;
+
+    /// Write the next block into `results`.
+    fn generate(&mut self, results: &mut Self::Results);
+}
+
+/// Copy words from `src` into `dest` as little-endian bytes, returning the
+/// number of words consumed and bytes filled. A word only partly copied
+/// counts as consumed.
+macro_rules! fill_via_chunks {
+    ($src:expr, $dest:expr, $ty:ty) => {{
+        const SIZE: usize = core::mem::size_of::<$ty>();
+        let filled = $dest.len().min($src.len() * SIZE);
+        let consumed = filled.div_ceil(SIZE);
+        for (chunk, word) in $dest[..filled].chunks_mut(SIZE).zip($src.iter()) {
+            chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+        }
+        (consumed, filled)
+    }};
+}
+
+/// `RngCore` output from a core producing blocks of `u32`.
+///
+/// Words are returned in order. A `u64` is two consecutive words, low word
+/// first, and may take the last word of one block and the first of the
+/// next. `fill_bytes` copies whole words little-endian, discarding the
+/// unused bytes of the last one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(Serialize))]
+#[cfg_attr(feature = "serde1", serde(bound(serialize = "R: Serialize, R::Results: Serialize")))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), derive(Deserialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), serde(bound(deserialize = "R: Deserialize<'de>, R::Results: Deserialize<'de>")))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), serde(try_from = "BlockRngRecord<R::Results, R>"))]
+pub struct BlockRng<R: BlockRngCore> {
+    results: R::Results,
+    index: usize,
+    core: R,
+}
+
+impl<R: BlockRngCore> BlockRng<R> {
+    /// Wrap `core`. No block is generated until output is requested.
+    pub fn new(core: R) -> Self {
+        let results = R::Results::default();
+        let index = results.as_ref().len();
+        Self {
+            results,
+            index,
+            core,
+        }
+    }
+
+    pub fn core(&self) -> &R {
+        &self.core
+    }
+
+    /// The core, for changing its state. Call `reset` or
+    /// `generate_and_set` afterwards so buffered words from the old state
+    /// are not returned.
+    pub fn core_mut(&mut self) -> &mut R {
+        &mut self.core
+    }
+
+    /// Index of the next unused word in the current block; the block length
+    /// once it is used up.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Discard the rest of the current block.
+    pub fn reset(&mut self) {
+        self.index = self.results.as_ref().len();
+    }
+
+    /// Generate a new block and continue from word `index` of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is past the end of the block.
+    pub fn generate_and_set(&mut self, index: usize) {
+        assert!(index <= self.results.as_ref().len());
+        self.core.generate(&mut self.results);
+        self.index = index;
+    }
+}
+
+impl<R: BlockRngCore<Item = u32>> RngCore for BlockRng<R> {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        if self.index >= self.results.as_ref().len() {
+            self.generate_and_set(0);
+        }
+        let value = self.results.as_ref()[self.index];
+        self.index += 1;
+        value
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        let len = self.results.as_ref().len();
+        let index = self.index;
+        if index + 1 < len {
+            self.index += 2;
+            let results = self.results.as_ref();
+            (u64::from(results[index + 1]) << 32) | u64::from(results[index])
+        } else if index + 1 == len {
+            // the low word is the last of this block
+            let lo = u64::from(self.results.as_ref()[index]);
+            self.generate_and_set(1);
+            (u64::from(self.results.as_ref()[0]) << 32) | lo
+        } else {
+            self.generate_and_set(2);
+            let results = self.results.as_ref();
+            (u64::from(results[1]) << 32) | u64::from(results[0])
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let mut filled = 0;
+        while filled < dest.len() {
+            if self.index >= self.results.as_ref().len() {
+                self.generate_and_set(0);
+            }
+            let (consumed, n) =
+                fill_via_chunks!(self.results.as_ref()[self.index..], dest[filled..], u32);
+            self.index += consumed;
+            filled += n;
+        }
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl<R: BlockRngCore + SeedableRng> SeedableRng for BlockRng<R> {
+    type Seed = R::Seed;
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::new(R::from_seed(seed))
+    }
+}
+
+/// `RngCore` output from a core producing blocks of `u64`.
+///
+/// `next_u32` returns the low half of a word and then its high half, so no
+/// bits are wasted; `next_u64` and `fill_bytes` always start at a fresh
+/// word, discarding a half-used one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(Serialize))]
+#[cfg_attr(feature = "serde1", serde(bound(serialize = "R: Serialize, R::Results: Serialize")))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), derive(Deserialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), serde(bound(deserialize = "R: Deserialize<'de>, R::Results: Deserialize<'de>")))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), serde(try_from = "BlockRng64Record<R::Results, R>"))]
+pub struct BlockRng64<R: BlockRngCore> {
+    results: R::Results,
+    index: usize,
+    half_used: bool,
+    core: R,
+}
+
+impl<R: BlockRngCore> BlockRng64<R> {
+    /// Wrap `core`. No block is generated until output is requested.
+    pub fn new(core: R) -> Self {
+        let results = R::Results::default();
+        let index = results.as_ref().len();
+        Self {
+            results,
+            index,
+            half_used: false,
+            core,
+        }
+    }
+
+    pub fn core(&self) -> &R {
+        &self.core
+    }
+
+    /// The core, for changing its state. Call `reset` or
+    /// `generate_and_set` afterwards so buffered words from the old state
+    /// are not returned.
+    pub fn core_mut(&mut self) -> &mut R {
+        &mut self.core
+    }
+
+    /// Index of the next unused word in the current block; the block length
+    /// once it is used up.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Discard the rest of the current block.
+    pub fn reset(&mut self) {
+        self.index = self.results.as_ref().len();
+        self.half_used = false;
+    }
+
+    /// Generate a new block and continue from word `index` of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is past the end of the block.
+    pub fn generate_and_set(&mut self, index: usize) {
+        assert!(index <= self.results.as_ref().len());
+        self.core.generate(&mut self.results);
+        self.index = index;
+        self.half_used = false;
+    }
+}
+
+impl<R: BlockRngCore<Item = u64>> RngCore for BlockRng64<R> {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        // a half-used word is at `index - 1`
+        let mut index = self.index - self.half_used as usize;
+        if index >= self.results.as_ref().len() {
+            self.core.generate(&mut self.results);
+            self.index = 0;
+            index = 0;
+        }
+        let shift = 32 * (self.half_used as usize);
+        self.half_used = !self.half_used;
+        self.index += self.half_used as usize;
+        (self.results.as_ref()[index] >> shift) as u32
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        if self.index >= self.results.as_ref().len() {
+            self.generate_and_set(0);
+        }
+        self.half_used = false;
+        let value = self.results.as_ref()[self.index];
+        self.index += 1;
+        value
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.half_used = false;
+        let mut filled = 0;
+        while filled < dest.len() {
+            if self.index >= self.results.as_ref().len() {
+                self.generate_and_set(0);
+            }
+            let (consumed, n) =
+                fill_via_chunks!(self.results.as_ref()[self.index..], dest[filled..], u64);
+            self.index += consumed;
+            filled += n;
+        }
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl<R: BlockRngCore + SeedableRng> SeedableRng for BlockRng64<R> {
+    type Seed = R::Seed;
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::new(R::from_seed(seed))
+    }
+}
+
+/// The encoded form of a block generator, generic over the field types so
+/// the derives do not need to bound `R::Results`. Decoding goes through it
+/// so an index past the block is rejected rather than trusted.
+#[cfg(any(feature = "serde1", feature = "candid"))]
+#[derive(Deserialize)]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
+struct BlockRngRecord<T, C> {
+    results: T,
+    index: usize,
+    core: C,
+}
+
+#[cfg(any(feature = "serde1", feature = "candid"))]
+impl<R: BlockRngCore> TryFrom<BlockRngRecord<R::Results, R>> for BlockRng<R> {
+    type Error = StateError;
+
+    fn try_from(record: BlockRngRecord<R::Results, R>) -> Result<Self, StateError> {
+        if record.index > record.results.as_ref().len() {
+            return Err(StateError::InvalidState);
+        }
+        Ok(Self {
+            results: record.results,
+            index: record.index,
+            core: record.core,
+        })
+    }
+}
+
+#[cfg(feature = "candid")]
+impl<R> candid::CandidType for BlockRng<R>
+where
+    R: BlockRngCore + candid::CandidType,
+    R::Results: candid::CandidType,
+{
+    fn _ty() -> candid::types::Type {
+        BlockRngRecord::<R::Results, R>::ty()
+    }
+
+    fn idl_serialize<S: candid::types::Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        BlockRngRecord {
+            results: &self.results,
+            index: self.index,
+            core: &self.core,
+        }
+        .idl_serialize(serializer)
+    }
+}
+
+/// As `BlockRngRecord`, with the half-used flag.
+#[cfg(any(feature = "serde1", feature = "candid"))]
+#[derive(Deserialize)]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
+struct BlockRng64Record<T, C> {
+    results: T,
+    index: usize,
+    half_used: bool,
+    core: C,
+}
+
+#[cfg(any(feature = "serde1", feature = "candid"))]
+impl<R: BlockRngCore> TryFrom<BlockRng64Record<R::Results, R>> for BlockRng64<R> {
+    type Error = StateError;
+
+    fn try_from(record: BlockRng64Record<R::Results, R>) -> Result<Self, StateError> {
+        // a half-used word is at `index - 1`, so there must be one
+        if record.index > record.results.as_ref().len() || (record.half_used && record.index == 0) {
+            return Err(StateError::InvalidState);
+        }
+        Ok(Self {
+            results: record.results,
+            index: record.index,
+            half_used: record.half_used,
+            core: record.core,
+        })
+    }
+}
+
+#[cfg(feature = "candid")]
+impl<R> candid::CandidType for BlockRng64<R>
+where
+    R: BlockRngCore + candid::CandidType,
+    R::Results: candid::CandidType,
+{
+    fn _ty() -> candid::types::Type {
+        BlockRng64Record::<R::Results, R>::ty()
+    }
+
+    fn idl_serialize<S: candid::types::Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        BlockRng64Record {
+            results: &self.results,
+            index: self.index,
+            half_used: self.half_used,
+            core: &self.core,
+        }
+        .idl_serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Blocks of consecutive numbers: 0..4, 4..8 and so on.
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde1", derive(Serialize))]
+    #[cfg_attr(any(feature = "serde1", feature = "candid"), derive(Deserialize))]
+    #[cfg_attr(feature = "candid", derive(candid::CandidType))]
+    struct Counter32(u32);
+
+    impl BlockRngCore for Counter32 {
+        type Item = u32;
+        type Results = [u32; 4];
+
+        fn generate(&mut self, results: &mut [u32; 4]) {
+            for r in results.iter_mut() {
+                *r = self.0;
+                self.0 += 1;
+            }
+        }
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    #[cfg_attr(feature = "serde1", derive(Serialize))]
+    #[cfg_attr(any(feature = "serde1", feature = "candid"), derive(Deserialize))]
+    #[cfg_attr(feature = "candid", derive(candid::CandidType))]
+    struct Counter64(u64);
+
+    impl BlockRngCore for Counter64 {
+        type Item = u64;
+        type Results = [u64; 3];
+
+        fn generate(&mut self, results: &mut [u64; 3]) {
+            for r in results.iter_mut() {
+                // distinct halves: high half is the low half plus 100
+                *r = ((self.0 + 100) << 32) | self.0;
+                self.0 += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn u32_blocks() {
+        let mut rng = BlockRng::new(Counter32(0));
+        assert_eq!(rng.next_u32(), 0);
+        assert_eq!(rng.next_u64(), (2 << 32) | 1);
+        // straddles blocks 0 and 1
+        assert_eq!(rng.next_u64(), (4 << 32) | 3);
+        assert_eq!(rng.index(), 1);
+
+        let mut bytes = [0u8; 13];
+        rng.fill_bytes(&mut bytes);
+        let expected: Vec<u8> = (5u32..9).flat_map(|w| w.to_le_bytes()).take(13).collect();
+        assert_eq!(bytes[..], expected[..]);
+        // the partly used word 8 is gone
+        assert_eq!(rng.next_u32(), 9);
+
+        rng.reset();
+        assert_eq!(rng.next_u32(), 12);
+        rng.generate_and_set(3);
+        assert_eq!(rng.next_u64(), (20 << 32) | 19);
+    }
+
+    #[test]
+    fn u64_blocks() {
+        let mut rng = BlockRng64::new(Counter64(0));
+        assert_eq!(rng.next_u32(), 0);
+        assert_eq!(rng.next_u32(), 100);
+        assert_eq!(rng.next_u32(), 1);
+        // the high half of word 1 is dropped
+        assert_eq!(rng.next_u64(), (102 << 32) | 2);
+        assert_eq!(rng.next_u32(), 3);
+        assert_eq!(rng.next_u32(), 103);
+
+        let mut bytes = [0u8; 12];
+        rng.fill_bytes(&mut bytes);
+        assert_eq!(bytes[..8], ((104u64 << 32) | 4).to_le_bytes());
+        assert_eq!(bytes[8..], ((105u64 << 32) | 5).to_le_bytes()[..4]);
+        assert_eq!(rng.next_u32(), 6);
+    }
+
+    #[test]
+    fn large_fills_span_blocks() {
+        let mut a = BlockRng::new(Counter32(0));
+        let mut b = BlockRng::new(Counter32(0));
+        let mut bytes = [0u8; 50];
+        a.fill_bytes(&mut bytes);
+        for chunk in bytes.chunks(4) {
+            let word = b.next_u32().to_le_bytes();
+            assert_eq!(chunk, &word[..chunk.len()]);
+        }
+        assert_eq!(a.next_u32(), b.next_u32());
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn serde_rejects_bad_index() {
+        let mut rng = BlockRng::new(Counter32(0));
+        let _ = rng.next_u32();
+        let json = serde_json::to_string(&rng).unwrap();
+        assert_eq!(json, r#"{"results":[0,1,2,3],"index":1,"core":4}"#);
+        let decoded: BlockRng<Counter32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, rng);
+
+        let json = r#"{"results":[0,1,2,3],"index":4,"core":4}"#;
+        assert!(serde_json::from_str::<BlockRng<Counter32>>(json).is_ok());
+        let json = r#"{"results":[0,1,2,3],"index":5,"core":4}"#;
+        assert!(serde_json::from_str::<BlockRng<Counter32>>(json).is_err());
+
+        let mut rng = BlockRng64::new(Counter64(0));
+        let _ = rng.next_u32();
+        let json = serde_json::to_string(&rng).unwrap();
+        let decoded: BlockRng64<Counter64> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, rng);
+
+        // past the block, and a half-used word before the first
+        let tampered = [
+            r#"{"results":[0,0,0],"index":4,"half_used":false,"core":0}"#,
+            r#"{"results":[0,0,0],"index":0,"half_used":true,"core":0}"#,
+        ];
+        for json in tampered {
+            assert!(serde_json::from_str::<BlockRng64<Counter64>>(json).is_err(), "{}", json);
+        }
+    }
+
+    #[cfg(feature = "candid")]
+    #[test]
+    fn candid_rejects_bad_index() {
+        fn decode(index: usize, half_used: bool) -> Result<BlockRng64<Counter64>, candid::Error> {
+            let record = BlockRng64Record {
+                results: [0u64; 3],
+                index,
+                half_used,
+                core: Counter64(0),
+            };
+            candid::decode_one(&candid::encode_one(record).unwrap())
+        }
+
+        assert!(decode(3, false).is_ok());
+        assert!(decode(3, true).is_ok());
+        assert!(decode(4, false).is_err());
+        assert!(decode(usize::MAX, false).is_err());
+        assert!(decode(0, true).is_err());
+    }
+}
@@ -0,0 +1,402 @@
+//! The ChaCha stream cipher as a random number generator.
+//!
+//! Unlike the PCG generators, ChaCha is cryptographically secure: its output
+//! cannot be predicted without the seed, even after observing earlier
+//! output. Use it for anything adversarial, such as lotteries.
+//!
+//! The input block is the original layout from Bernstein's paper: a 256-bit
+//! key (the seed), a 64-bit block counter and a 64-bit stream id. Output
+//! matches the `rand_chacha` crate word for word.
+
+use crate::block::{BlockRng, BlockRngCore};
+use crate::core::{self as rng_core, Error, RngCore, SeedableRng};
+use crate::stable::{PersistentState, StateError};
+
+/// Words per ChaCha block.
+const BLOCK_WORDS: usize = 16;
+
+/// Word positions wrap at 2^68: 2^64 blocks of 16 words.
+const WORD_POS_MASK: u128 = (1 << 68) - 1;
+
+/// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+#[inline(always)]
+fn quarter_round(x: &mut [u32; BLOCK_WORDS], a: usize, b: usize, c: usize, d: usize) {
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(16);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(12);
+    x[a] = x[a].wrapping_add(x[b]);
+    x[d] = (x[d] ^ x[a]).rotate_left(8);
+    x[c] = x[c].wrapping_add(x[d]);
+    x[b] = (x[b] ^ x[c]).rotate_left(7);
+}
+
+/// Compute block `counter` of stream `stream` under `key`.
+fn chacha_block(
+    key: &[u32; 8],
+    counter: u64,
+    stream: u64,
+    rounds: usize,
+    out: &mut [u32; BLOCK_WORDS],
+) {
+    let mut input = [0u32; BLOCK_WORDS];
+    input[..4].copy_from_slice(&CONSTANTS);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter as u32;
+    input[13] = (counter >> 32) as u32;
+    input[14] = stream as u32;
+    input[15] = (stream >> 32) as u32;
+
+    let mut x = input;
+    for _ in 0..rounds / 2 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+    for ((o, x), i) in out.iter_mut().zip(x).zip(input) {
+        *o = x.wrapping_add(i);
+    }
+}
+
+macro_rules! chacha_impl {
+    ($name:ident, $core:ident, $rounds:expr, $kind:expr, $doc:expr) => {
+        /// The block function behind
+        #[doc = concat!("`", stringify!($name), "`.")]
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        #[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+        #[cfg_attr(any(feature = "serde1", feature = "candid"), derive(serde::Deserialize))]
+        #[cfg_attr(feature = "candid", derive(candid::CandidType))]
+        pub struct $core {
+            key: [u32; 8],
+            /// Counter of the next block to generate.
+            counter: u64,
+            stream: u64,
+        }
+
+        impl BlockRngCore for $core {
+            type Item = u32;
+            type Results = [u32; BLOCK_WORDS];
+
+            fn generate(&mut self, results: &mut Self::Results) {
+                chacha_block(&self.key, self.counter, self.stream, $rounds, results);
+                self.counter = self.counter.wrapping_add(1);
+            }
+        }
+
+        impl SeedableRng for $core {
+            type Seed = [u8; 32];
+
+            fn from_seed(seed: [u8; 32]) -> Self {
+                let mut key = [0u32; 8];
+                rng_core::read_u32_into(&seed, &mut key);
+                Self {
+                    key,
+                    counter: 0,
+                    stream: 0,
+                }
+            }
+        }
+
+        #[doc = $doc]
+        #[derive(Clone, Debug)]
+        #[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+        #[cfg_attr(any(feature = "serde1", feature = "candid"), derive(serde::Deserialize))]
+        #[cfg_attr(feature = "candid", derive(candid::CandidType))]
+        pub struct $name {
+            rng: BlockRng<$core>,
+        }
+
+        impl $name {
+            /// The seed this generator was created from.
+            pub fn seed(&self) -> [u8; 32] {
+                let mut seed = [0u8; 32];
+                for (chunk, word) in seed.chunks_exact_mut(4).zip(self.rng.core().key) {
+                    chunk.copy_from_slice(&word.to_le_bytes());
+                }
+                seed
+            }
+
+            pub fn stream(&self) -> u64 {
+                self.rng.core().stream
+            }
+
+            /// Switch to stream `stream`, keeping the word position.
+            pub fn set_stream(&mut self, stream: u64) {
+                self.rng.core_mut().stream = stream;
+                let index = self.rng.index();
+                if index < BLOCK_WORDS {
+                    // regenerate the current block from the new stream
+                    let core = self.rng.core_mut();
+                    core.counter = core.counter.wrapping_sub(1);
+                    self.rng.generate_and_set(index);
+                }
+            }
+
+            /// The index of the next 32-bit word in the stream, modulo 2^68.
+            pub fn word_pos(&self) -> u128 {
+                // `counter` is one block ahead of the buffered block
+                let next_block = u128::from(self.rng.core().counter) * BLOCK_WORDS as u128;
+                (next_block + self.rng.index() as u128).wrapping_sub(BLOCK_WORDS as u128)
+                    & WORD_POS_MASK
+            }
+
+            /// Jump to word `pos` of the stream. Only the low 68 bits are
+            /// used.
+            pub fn set_word_pos(&mut self, pos: u128) {
+                let pos = pos & WORD_POS_MASK;
+                self.rng.core_mut().counter = (pos / BLOCK_WORDS as u128) as u64;
+                self.rng
+                    .generate_and_set((pos % BLOCK_WORDS as u128) as usize);
+            }
+        }
+
+        /// Generators are equal if they produce the same output, whether or
+        /// not the current block has been generated yet.
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.seed() == other.seed()
+                    && self.stream() == other.stream()
+                    && self.word_pos() == other.word_pos()
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl RngCore for $name {
+            #[inline]
+            fn next_u32(&mut self) -> u32 {
+                self.rng.next_u32()
+            }
+
+            #[inline]
+            fn next_u64(&mut self) -> u64 {
+                self.rng.next_u64()
+            }
+
+            #[inline]
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                self.rng.fill_bytes(dest)
+            }
+
+            #[inline]
+            fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+                self.rng.try_fill_bytes(dest)
+            }
+        }
+
+        impl SeedableRng for $name {
+            type Seed = [u8; 32];
+
+            fn from_seed(seed: [u8; 32]) -> Self {
+                Self {
+                    rng: BlockRng::from_seed(seed),
+                }
+            }
+        }
+
+        /// Stored as the seed, the stream id and the word position, all
+        /// little-endian.
+        impl PersistentState for $name {
+            const KIND: u8 = $kind;
+            const STATE_LEN: usize = 56;
+
+            fn write_state(&self, out: &mut [u8]) {
+                out[..32].copy_from_slice(&self.seed());
+                out[32..40].copy_from_slice(&self.stream().to_le_bytes());
+                out[40..].copy_from_slice(&self.word_pos().to_le_bytes());
+            }
+
+            fn read_state(bytes: &[u8]) -> Result<Self, StateError> {
+                if bytes.len() != Self::STATE_LEN {
+                    return Err(StateError::Truncated);
+                }
+                let word_pos = u128::from_le_bytes(bytes[40..].try_into().unwrap());
+                if word_pos > WORD_POS_MASK {
+                    return Err(StateError::InvalidState);
+                }
+                let mut rng = Self::from_seed(bytes[..32].try_into().unwrap());
+                rng.rng.core_mut().stream = u64::from_le_bytes(bytes[32..40].try_into().unwrap());
+                rng.set_word_pos(word_pos);
+                Ok(rng)
+            }
+        }
+    };
+}
+
+chacha_impl!(
+    ChaCha8Rng,
+    ChaCha8Core,
+    8,
+    3,
+    "ChaCha with 8 rounds: the fastest variant, with no known practical attack."
+);
+chacha_impl!(
+    ChaCha12Rng,
+    ChaCha12Core,
+    12,
+    4,
+    "ChaCha with 12 rounds: a conservative middle ground."
+);
+chacha_impl!(
+    ChaCha20Rng,
+    ChaCha20Core,
+    20,
+    5,
+    "ChaCha with 20 rounds, as standardised in RFC 8439."
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::{decode_state, encode_state};
+
+    fn block<R: RngCore>(rng: &mut R) -> [u32; 16] {
+        let mut out = [0u32; 16];
+        for w in out.iter_mut() {
+            *w = rng.next_u32();
+        }
+        out
+    }
+
+    #[test]
+    fn chacha20_ietf_vectors() {
+        // test vectors 1 to 5 of draft-nir-cfrg-chacha20-poly1305-04
+        let mut rng = ChaCha20Rng::from_seed([0; 32]);
+        assert_eq!(
+            block(&mut rng),
+            [
+                0xade0b876, 0x903df1a0, 0xe56a5d40, 0x28bd8653, 0xb819d2bd, 0x1aed8da0, 0xccef36a8,
+                0xc70d778b, 0x7c5941da, 0x8d485751, 0x3fe02477, 0x374ad8b8, 0xf4b8436a, 0x1ca11815,
+                0x69b687c3, 0x8665eeb2,
+            ]
+        );
+        assert_eq!(
+            block(&mut rng),
+            [
+                0xbee7079f, 0x7a385155, 0x7c97ba98, 0x0d082d73, 0xa0290fcb, 0x6965e348, 0x3e53c612,
+                0xed7aee32, 0x7621b729, 0x434ee69c, 0xb03371d5, 0xd539d874, 0x281fed31, 0x45fb0a51,
+                0x1f0ae1ac, 0x6f4d794b,
+            ]
+        );
+
+        let mut seed = [0u8; 32];
+        seed[31] = 1;
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        rng.set_word_pos(16);
+        assert_eq!(
+            block(&mut rng),
+            [
+                0x2452eb3a, 0x9249f8ec, 0x8d829d9b, 0xddd4ceb1, 0xe8252083, 0x60818b01, 0xf38422b8,
+                0x5aaa49c9, 0xbb00ca8e, 0xda3ba7b4, 0xc4b592d1, 0xfdf2732f, 0x4436274e, 0x2561b3c8,
+                0xebdd4aa6, 0xa0136c00,
+            ]
+        );
+
+        let mut seed = [0u8; 32];
+        seed[1] = 0xff;
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        for _ in 0..32 {
+            rng.next_u32();
+        }
+        assert_eq!(
+            block(&mut rng),
+            [
+                0xfb4dd572, 0x4bc42ef1, 0xdf922636, 0x327f1394, 0xa78dea8f, 0x5e269039, 0xa1bebbc1,
+                0xcaf09aae, 0xa25ab213, 0x48a6b46c, 0x1b9d9bcb, 0x092c5be6, 0x546ca624, 0x1bec45d5,
+                0x87f47473, 0x96f0992e,
+            ]
+        );
+        assert_eq!(rng.word_pos(), 48);
+
+        // the 96-bit nonce 0,0,0,0,0,0,0,0,0,0,0,2 sits in the stream id
+        let mut rng = ChaCha20Rng::from_seed([0; 32]);
+        rng.set_stream(2 << 56);
+        assert_eq!(
+            block(&mut rng),
+            [
+                0x374dc6c2, 0x3736d58c, 0xb904e24a, 0xcd3f93ef, 0x88228b1a, 0x96a4dfb3, 0x5b76ab72,
+                0xc727ee54, 0x0e0e978a, 0xf3145c95, 0x1b748ea8, 0xf786c297, 0x99c28f5f, 0x628314e8,
+                0x398a19fa, 0x6ded1b53,
+            ]
+        );
+    }
+
+    #[test]
+    fn reduced_round_vectors() {
+        // TC1 of draft-strombergson-chacha-test-vectors-00
+        let mut bytes = [0u8; 16];
+        ChaCha8Rng::from_seed([0; 32]).fill_bytes(&mut bytes);
+        assert_eq!(
+            bytes,
+            [
+                0x3e, 0x00, 0xef, 0x2f, 0x89, 0x5f, 0x40, 0xd6, 0x7f, 0x5b, 0xb8, 0xe8, 0x1f, 0x09,
+                0xa5, 0xa1,
+            ]
+        );
+        let mut rng = ChaCha12Rng::from_seed([0; 32]);
+        assert_eq!(
+            block(&mut rng)[..4],
+            [0x6a9af49b, 0x53f95507, 0x12ce1f81, 0xd583265f]
+        );
+    }
+
+    #[test]
+    fn output_across_blocks() {
+        let mut seed = [0u8; 32];
+        for (i, b) in seed.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        let mut rng = ChaCha8Rng::from_seed(seed);
+        rng.set_stream(0x0123_4567_89ab_cdef);
+        rng.set_word_pos(15);
+        // the first u64 straddles two blocks
+        assert_eq!(rng.next_u64(), 0xddf1dd9b2266ecf8);
+        assert_eq!(rng.next_u64(), 0x441e54fa2f22922e);
+        let mut bytes = [0u8; 7];
+        rng.fill_bytes(&mut bytes);
+        assert_eq!(bytes, [131, 235, 254, 29, 232, 249, 109]);
+        assert_eq!(rng.word_pos(), 21);
+        assert_eq!(rng.seed(), seed);
+    }
+
+    #[test]
+    fn word_pos_and_streams() {
+        let mut rng = ChaCha20Rng::seed_from_u64(9);
+        let mut clone = rng.clone();
+        rng.set_stream(51);
+        for _ in 0..7 {
+            assert_ne!(rng.next_u32(), clone.next_u32());
+        }
+        // switching part way through a block keeps the position
+        clone.set_stream(51);
+        assert_eq!(block(&mut rng), block(&mut clone));
+
+        let last = (1u128 << 68) - 1;
+        rng.set_word_pos(last);
+        assert_eq!(rng.word_pos(), last);
+        rng.next_u32();
+        assert_eq!(rng.word_pos(), 0);
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let mut rng = ChaCha12Rng::seed_from_u64(4);
+        rng.set_stream(3);
+        rng.set_word_pos(100);
+        let bytes = encode_state(&rng);
+        let mut restored: ChaCha12Rng = decode_state(&bytes).unwrap();
+        assert_eq!(restored, rng);
+        assert_eq!(restored.next_u64(), rng.next_u64());
+        assert_eq!(
+            decode_state::<ChaCha20Rng>(&bytes),
+            Err(StateError::WrongGenerator)
+        );
+    }
+}
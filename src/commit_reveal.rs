@@ -0,0 +1,438 @@
+//! Commit–reveal randomness for multi-party games.
+//!
+//! Each participant commits to a secret before any secret is revealed, so
+//! nobody can pick theirs after seeing the others. Once the reveal phase
+//! closes, the verified secrets are hashed together, optionally with
+//! `raw_rand` output, into the seed of the round's generator.
+//!
+//! Times are whatever clock the caller uses, typically `ic_cdk::api::time()`
+//! in nanoseconds; deadlines are exclusive.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::core::{Error, SeedableRng};
+use crate::entropy::{self, EntropySource};
+use crate::hash::Sha256;
+use crate::utils::Rand64;
+
+const COMMIT_DOMAIN: &[u8] = b"ic_rand/commit/v1";
+const SEED_DOMAIN: &[u8] = b"ic_rand/reveal/v1";
+
+/// A hash commitment to a secret.
+pub type Commitment = [u8; 32];
+
+/// The commitment `participant` submits for `secret`.
+///
+/// The participant is part of the hash, so one participant cannot copy
+/// another's commitment and later reveal the same secret.
+pub fn commitment(participant: &[u8], secret: &[u8; 32]) -> Commitment {
+    let mut h = Sha256::new();
+    h.update(COMMIT_DOMAIN);
+    h.update(&(participant.len() as u32).to_le_bytes());
+    h.update(participant);
+    h.update(secret);
+    h.finalize()
+}
+
+/// The phase of a round at a given time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Commitments are accepted.
+    Commit,
+    /// Commitments are closed and secrets can be revealed.
+    Reveal,
+    /// The reveal deadline has passed; the round can only be finalized.
+    Closed,
+}
+
+/// What to do with participants who committed but did not reveal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MissingReveals {
+    /// Refuse to finalize, e.g. to void the round and refund stakes.
+    Abort,
+    /// Finalize with the secrets that were revealed. The outcome lists the
+    /// defaulters so they can be penalized.
+    ///
+    /// Withholding a reveal lets a participant choose between two outcomes;
+    /// mixing in `raw_rand` output fetched after the reveal deadline takes
+    /// that choice away.
+    Exclude,
+}
+
+/// One commit–reveal round.
+#[derive(Clone, Debug)]
+pub struct Round {
+    commit_deadline: u64,
+    reveal_deadline: u64,
+    commitments: BTreeMap<Vec<u8>, Commitment>,
+    reveals: BTreeMap<Vec<u8>, [u8; 32]>,
+}
+
+impl Round {
+    /// A round accepting commitments until `commit_deadline` and reveals
+    /// from then until `reveal_deadline`.
+    pub fn new(commit_deadline: u64, reveal_deadline: u64) -> Result<Self, ProtocolError> {
+        if reveal_deadline <= commit_deadline {
+            return Err(ProtocolError::InvalidDeadlines);
+        }
+        Ok(Self {
+            commit_deadline,
+            reveal_deadline,
+            commitments: BTreeMap::new(),
+            reveals: BTreeMap::new(),
+        })
+    }
+
+    pub fn phase(&self, now: u64) -> Phase {
+        if now < self.commit_deadline {
+            Phase::Commit
+        } else if now < self.reveal_deadline {
+            Phase::Reveal
+        } else {
+            Phase::Closed
+        }
+    }
+
+    /// Record the commitment of `participant`.
+    pub fn commit(
+        &mut self,
+        participant: &[u8],
+        commitment: Commitment,
+        now: u64,
+    ) -> Result<(), ProtocolError> {
+        if self.phase(now) != Phase::Commit {
+            return Err(ProtocolError::CommitClosed);
+        }
+        if self.commitments.contains_key(participant) {
+            return Err(ProtocolError::AlreadyCommitted);
+        }
+        self.commitments.insert(participant.to_vec(), commitment);
+        Ok(())
+    }
+
+    /// Verify and record the secret of `participant`.
+    pub fn reveal(
+        &mut self,
+        participant: &[u8],
+        secret: [u8; 32],
+        now: u64,
+    ) -> Result<(), ProtocolError> {
+        match self.phase(now) {
+            Phase::Commit => return Err(ProtocolError::RevealNotOpen),
+            Phase::Reveal => {}
+            Phase::Closed => return Err(ProtocolError::RevealClosed),
+        }
+        let expected = self
+            .commitments
+            .get(participant)
+            .ok_or(ProtocolError::NotCommitted)?;
+        if self.reveals.contains_key(participant) {
+            return Err(ProtocolError::AlreadyRevealed);
+        }
+        if commitment(participant, &secret) != *expected {
+            return Err(ProtocolError::CommitmentMismatch);
+        }
+        self.reveals.insert(participant.to_vec(), secret);
+        Ok(())
+    }
+
+    /// The number of participants who committed.
+    pub fn participants(&self) -> usize {
+        self.commitments.len()
+    }
+
+    /// Participants who committed but have not revealed, in order.
+    pub fn missing_reveals(&self) -> impl Iterator<Item = &[u8]> {
+        self.commitments
+            .keys()
+            .filter(|p| !self.reveals.contains_key(*p))
+            .map(Vec::as_slice)
+    }
+
+    /// Combine the revealed secrets, and `entropy` if given, into the
+    /// round's outcome.
+    ///
+    /// Before the reveal deadline this only succeeds once every participant
+    /// has revealed.
+    pub fn finalize(
+        &self,
+        policy: MissingReveals,
+        entropy: Option<[u8; 32]>,
+        now: u64,
+    ) -> Result<Outcome, ProtocolError> {
+        let missing: Vec<Vec<u8>> = self.missing_reveals().map(<[u8]>::to_vec).collect();
+        match self.phase(now) {
+            Phase::Commit => return Err(ProtocolError::RevealNotOpen),
+            Phase::Reveal if !missing.is_empty() => return Err(ProtocolError::RevealStillOpen),
+            _ => {}
+        }
+        if !missing.is_empty() && policy == MissingReveals::Abort {
+            return Err(ProtocolError::MissingReveals(missing.len()));
+        }
+        if self.reveals.is_empty() && entropy.is_none() {
+            return Err(ProtocolError::NoRandomness);
+        }
+
+        let mut h = Sha256::new();
+        h.update(SEED_DOMAIN);
+        h.update(&(self.reveals.len() as u32).to_le_bytes());
+        for (participant, secret) in &self.reveals {
+            h.update(&(participant.len() as u32).to_le_bytes());
+            h.update(participant);
+            h.update(secret);
+        }
+        match &entropy {
+            Some(bytes) => {
+                h.update(&[1]);
+                h.update(bytes);
+            }
+            None => h.update(&[0]),
+        }
+
+        Ok(Outcome {
+            seed: h.finalize(),
+            revealed: self.reveals.keys().cloned().collect(),
+            missing,
+        })
+    }
+
+    /// Like `finalize`, mixing in 32 bytes fetched from `source`.
+    ///
+    /// The round is checked before fetching, so no entropy is spent on a
+    /// round that cannot be finalized.
+    pub async fn finalize_with<S>(
+        &self,
+        policy: MissingReveals,
+        source: &mut S,
+        now: u64,
+    ) -> Result<Outcome, ProtocolError>
+    where
+        S: EntropySource + ?Sized,
+    {
+        // placeholder entropy: this only checks the phase and the reveals
+        self.finalize(policy, Some([0; 32]), now)?;
+        let bytes = source.fetch_seed().await?;
+        self.finalize(policy, Some(bytes), now)
+    }
+}
+
+/// The result of a finalized round.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    /// The combined seed.
+    pub seed: [u8; 32],
+    /// Participants whose secrets went into the seed, in order.
+    pub revealed: Vec<Vec<u8>>,
+    /// Participants who committed but did not reveal, in order.
+    pub missing: Vec<Vec<u8>>,
+}
+
+impl Outcome {
+    /// The round's generator.
+    pub fn rng(&self) -> Rand64 {
+        Rand64::from_seed(self.seed)
+    }
+
+    /// A generator of any type seeded from the round.
+    pub fn rng_as<G: SeedableRng>(&self) -> G {
+        entropy::seed_from_bytes(self.seed)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// The reveal deadline is not after the commit deadline.
+    InvalidDeadlines,
+    /// The commit phase is over.
+    CommitClosed,
+    /// The participant has already committed.
+    AlreadyCommitted,
+    /// The commit phase is still running.
+    RevealNotOpen,
+    /// The reveal deadline has passed.
+    RevealClosed,
+    /// The participant did not commit in this round.
+    NotCommitted,
+    /// The participant has already revealed.
+    AlreadyRevealed,
+    /// The secret does not match the participant's commitment.
+    CommitmentMismatch,
+    /// Some participants have not revealed and the reveal phase is open.
+    RevealStillOpen,
+    /// This many participants did not reveal.
+    MissingReveals(usize),
+    /// Nothing was revealed and no entropy was supplied.
+    NoRandomness,
+    /// Fetching entropy failed.
+    Entropy(Error),
+}
+
+impl From<Error> for ProtocolError {
+    fn from(err: Error) -> Self {
+        ProtocolError::Entropy(err)
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::InvalidDeadlines => {
+                f.write_str("reveal deadline must be after commit deadline")
+            }
+            ProtocolError::CommitClosed => f.write_str("commit phase is closed"),
+            ProtocolError::AlreadyCommitted => f.write_str("participant already committed"),
+            ProtocolError::RevealNotOpen => f.write_str("reveal phase has not started"),
+            ProtocolError::RevealClosed => f.write_str("reveal phase is closed"),
+            ProtocolError::NotCommitted => f.write_str("participant did not commit"),
+            ProtocolError::AlreadyRevealed => f.write_str("participant already revealed"),
+            ProtocolError::CommitmentMismatch => f.write_str("secret does not match commitment"),
+            ProtocolError::RevealStillOpen => {
+                f.write_str("reveal phase is still open and reveals are missing")
+            }
+            ProtocolError::MissingReveals(n) => write!(f, "{} participants did not reveal", n),
+            ProtocolError::NoRandomness => f.write_str("no secrets revealed and no entropy"),
+            ProtocolError::Entropy(err) => write!(f, "fetching entropy failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::{block_on, MockEntropy};
+    use crate::RngCore;
+
+    const ALICE: &[u8] = b"alice";
+    const BOB: &[u8] = b"bob";
+
+    fn round_with_commits() -> Round {
+        let mut round = Round::new(10, 20).unwrap();
+        round.commit(ALICE, commitment(ALICE, &[1; 32]), 0).unwrap();
+        round.commit(BOB, commitment(BOB, &[2; 32]), 5).unwrap();
+        round
+    }
+
+    #[test]
+    fn full_round() {
+        let mut round = round_with_commits();
+        assert_eq!(round.phase(9), Phase::Commit);
+        assert_eq!(
+            round.reveal(ALICE, [1; 32], 9),
+            Err(ProtocolError::RevealNotOpen)
+        );
+
+        round.reveal(BOB, [2; 32], 10).unwrap();
+        assert_eq!(
+            round.finalize(MissingReveals::Exclude, None, 11),
+            Err(ProtocolError::RevealStillOpen)
+        );
+        round.reveal(ALICE, [1; 32], 12).unwrap();
+
+        // everyone revealed, so the round can finish early
+        let outcome = round.finalize(MissingReveals::Abort, None, 13).unwrap();
+        assert_eq!(outcome.revealed, [ALICE.to_vec(), BOB.to_vec()]);
+        assert!(outcome.missing.is_empty());
+        assert_eq!(
+            outcome,
+            round.finalize(MissingReveals::Abort, None, 30).unwrap()
+        );
+        let mut rng = outcome.rng();
+        let mut replay = Rand64::from_seed(outcome.seed);
+        assert_eq!(rng.next_u64(), replay.next_u64());
+    }
+
+    #[test]
+    fn commitments_are_enforced() {
+        let mut round = round_with_commits();
+        assert_eq!(
+            round.commit(ALICE, [0; 32], 1),
+            Err(ProtocolError::AlreadyCommitted)
+        );
+        assert_eq!(
+            round.commit(b"carol", [0; 32], 10),
+            Err(ProtocolError::CommitClosed)
+        );
+        assert_eq!(
+            round.reveal(ALICE, [2; 32], 10),
+            Err(ProtocolError::CommitmentMismatch)
+        );
+        assert_eq!(
+            round.reveal(b"carol", [3; 32], 10),
+            Err(ProtocolError::NotCommitted)
+        );
+        round.reveal(ALICE, [1; 32], 10).unwrap();
+        assert_eq!(
+            round.reveal(ALICE, [1; 32], 11),
+            Err(ProtocolError::AlreadyRevealed)
+        );
+        assert_eq!(
+            round.reveal(BOB, [2; 32], 20),
+            Err(ProtocolError::RevealClosed)
+        );
+        assert_eq!(
+            Round::new(5, 5).unwrap_err(),
+            ProtocolError::InvalidDeadlines
+        );
+    }
+
+    #[test]
+    fn missing_reveals_after_timeout() {
+        let mut round = round_with_commits();
+        round.reveal(ALICE, [1; 32], 15).unwrap();
+        assert_eq!(round.missing_reveals().collect::<Vec<_>>(), [BOB]);
+
+        assert_eq!(
+            round.finalize(MissingReveals::Abort, None, 20),
+            Err(ProtocolError::MissingReveals(1))
+        );
+        let outcome = round.finalize(MissingReveals::Exclude, None, 20).unwrap();
+        assert_eq!(outcome.revealed, [ALICE.to_vec()]);
+        assert_eq!(outcome.missing, [BOB.to_vec()]);
+
+        let empty = Round::new(10, 20).unwrap();
+        assert_eq!(
+            empty.finalize(MissingReveals::Exclude, None, 20),
+            Err(ProtocolError::NoRandomness)
+        );
+        assert!(empty
+            .finalize(MissingReveals::Exclude, Some([0; 32]), 20)
+            .is_ok());
+    }
+
+    #[test]
+    fn entropy_is_mixed_in() {
+        let mut round = round_with_commits();
+        round.reveal(ALICE, [1; 32], 15).unwrap();
+        round.reveal(BOB, [2; 32], 15).unwrap();
+
+        let plain = round.finalize(MissingReveals::Abort, None, 20).unwrap();
+        let mut source = MockEntropy::new(1);
+        let mixed = block_on(round.finalize_with(MissingReveals::Abort, &mut source, 20)).unwrap();
+        assert_ne!(plain.seed, mixed.seed);
+        assert_eq!(source.fetch_count(), 1);
+
+        let mut replay = MockEntropy::new(1);
+        let again = block_on(round.finalize_with(MissingReveals::Abort, &mut replay, 20)).unwrap();
+        assert_eq!(mixed, again);
+
+        let mut failing = MockEntropy::unavailable();
+        assert_eq!(
+            block_on(round.finalize_with(MissingReveals::Abort, &mut failing, 20)),
+            Err(ProtocolError::Entropy(Error::Unavailable))
+        );
+    }
+
+    #[test]
+    fn invalid_rounds_do_not_fetch_entropy() {
+        let round = round_with_commits();
+        let mut source = MockEntropy::new(1);
+        assert_eq!(
+            block_on(round.finalize_with(MissingReveals::Abort, &mut source, 20)),
+            Err(ProtocolError::MissingReveals(2))
+        );
+        assert_eq!(source.fetch_count(), 0);
+    }
+}
@@ -1,5 +1,172 @@
+use std::fmt;
+
+use crate::small::SplitMix64;
+
+/// The core of a random number generator.
+///
+/// This only covers raw output: 32-bit words, 64-bit words and byte
+/// buffers. Conversions to other types, ranges and distributions are built
+/// on top of it, so every distribution in this crate works with any type
+/// implementing `RngCore`.
+pub trait RngCore {
+    /// Return the next random `u32`.
+    fn next_u32(&mut self) -> u32;
+
+    /// Return the next random `u64`.
+    fn next_u64(&mut self) -> u64;
+
+    /// Fill `dest` with random data.
+    fn fill_bytes(&mut self, dest: &mut [u8]);
+
+    /// Fill `dest` with random data, reporting failure of the underlying
+    /// source instead of panicking.
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error>;
+}
+
+impl<R: RngCore + ?Sized> RngCore for &mut R {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        (**self).next_u32()
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        (**self).next_u64()
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        (**self).fill_bytes(dest)
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        (**self).try_fill_bytes(dest)
+    }
+}
+
+impl<R: RngCore + ?Sized> RngCore for Box<R> {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        (**self).next_u32()
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        (**self).next_u64()
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        (**self).fill_bytes(dest)
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        (**self).try_fill_bytes(dest)
+    }
+}
+
+/// A random number generator that can be explicitly seeded.
+///
+/// Every generator in this crate is seeded through this trait, so code that
+/// obtains entropy (from the management canister, a parent generator or a
+/// fixed test value) does not need to know which generator it is seeding.
+pub trait SeedableRng: Sized {
+    /// Seed type, a byte array of the generator's natural seed length.
+    type Seed: Sized + Default + AsMut<[u8]>;
+
+    /// Create a new generator from `seed`.
+    ///
+    /// Seeds should have good entropy; use `seed_from_u64` when only a small
+    /// number is available.
+    fn from_seed(seed: Self::Seed) -> Self;
+
+    /// Create a new generator from a `u64` seed.
+    ///
+    /// The value is expanded to a full `Seed` with `SplitMix64`, so nearby
+    /// inputs such as `0`, `1` and `2` give unrelated generators.
+    fn seed_from_u64(state: u64) -> Self {
+        let mut seed = Self::Seed::default();
+        SplitMix64::new(state).fill_bytes(seed.as_mut());
+        Self::from_seed(seed)
+    }
+
+    /// Create a new generator seeded from the output of `rng`.
+    ///
+    /// This is how a child generator is derived from a parent. The child's
+    /// stream is only as unpredictable as the parent's.
+    fn from_rng<R: RngCore>(mut rng: R) -> Result<Self, Error> {
+        let mut seed = Self::Seed::default();
+        rng.try_fill_bytes(seed.as_mut())?;
+        Ok(Self::from_seed(seed))
+    }
+}
+
+/// Error returned by a fallible random source.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The source of randomness is not available.
+    Unavailable,
+    /// The generator has not been seeded yet.
+    Unseeded,
+    /// The operating system's random source failed with this error code.
+    Os(i32),
+    /// The generator is already in use, e.g. by an enclosing `with_rng`.
+    Busy,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Unavailable => f.write_str("the source of randomness is unavailable"),
+            Error::Unseeded => f.write_str("the generator has not been seeded"),
+            Error::Os(code) => write!(f, "the OS random source failed (os error {})", code),
+            Error::Busy => f.write_str("the generator is already in use"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Implement `next_u64` via `next_u32`, little-endian order.
+pub fn next_u64_via_u32<R: RngCore + ?Sized>(rng: &mut R) -> u64 {
+    let x = u64::from(rng.next_u32());
+    let y = u64::from(rng.next_u32());
+    (y << 32) | x
+}
+
+/// Implement `fill_bytes` via `next_u64`, little-endian order.
+///
+/// Whole words are consumed; the unused bytes of the last word are
+/// discarded.
+pub fn fill_bytes_via_next_u64<R: RngCore + ?Sized>(rng: &mut R, dest: &mut [u8]) {
+    let mut chunks = dest.chunks_exact_mut(8);
+    for chunk in &mut chunks {
+        chunk.copy_from_slice(&rng.next_u64().to_le_bytes());
+    }
+    let rest = chunks.into_remainder();
+    if !rest.is_empty() {
+        let n = rest.len();
+        rest.copy_from_slice(&rng.next_u64().to_le_bytes()[..n]);
+    }
+}
+
+/// Implement `fill_bytes` via `next_u32`, little-endian order.
+pub fn fill_bytes_via_next_u32<R: RngCore + ?Sized>(rng: &mut R, dest: &mut [u8]) {
+    let mut chunks = dest.chunks_exact_mut(4);
+    for chunk in &mut chunks {
+        chunk.copy_from_slice(&rng.next_u32().to_le_bytes());
+    }
+    let rest = chunks.into_remainder();
+    if !rest.is_empty() {
+        let n = rest.len();
+        rest.copy_from_slice(&rng.next_u32().to_le_bytes()[..n]);
+    }
+}
+
 pub fn read_u32_into(src: &[u8], dst: &mut [u32]) {
-    assert!(src.len() > 4 * dst.len());
+    assert!(src.len() >= 4 * dst.len());
     for (out, chunk) in dst.iter_mut().zip(src.chunks_exact(4)) {
         *out = u32::from_le_bytes(chunk.try_into().unwrap());
     }
@@ -12,4 +179,3 @@ pub fn read_u64_into(src: &[u8], dst: &mut [u64]) {
         *out = u64::from_le_bytes(chunk.try_into().unwrap());
     }
 }
-
@@ -0,0 +1,70 @@
+//! A `getrandom` backend for canisters.
+//!
+//! `getrandom` has no entropy source on `wasm32-unknown-unknown`, so crates
+//! depending on it fail to build for canisters unless a custom backend is
+//! registered. With the `getrandom` feature this crate registers one that
+//! reads from the canister-global generator, a ChaCha20 stream seeded from
+//! `raw_rand`.
+//!
+//! Only one crate in a canister may register a backend. Seed the global
+//! generator as early as possible, e.g. with `global::schedule_reseed` in
+//! `init` and `post_upgrade`; until it is seeded, `getrandom` calls fail
+//! with `UNSEEDED` instead of returning predictable bytes.
+//!
+//! Query calls discard their state changes, including the generator's, so
+//! `getrandom` in a query returns the same bytes as every other query until
+//! the next update call advances the generator. Dependents that expect
+//! fresh values per call, such as `uuid` generating ids, should only run in
+//! update calls.
+
+use core::num::NonZeroU32;
+
+use crate::core::{Error, RngCore};
+use crate::global;
+
+/// The `getrandom` error code returned before the canister-global generator
+/// is seeded.
+pub const UNSEEDED: u32 = getrandom::Error::CUSTOM_START + 1;
+
+/// The `getrandom` error code returned when the canister-global generator
+/// is already in use, i.e. `getrandom` was called from inside `with_rng`.
+pub const BUSY: u32 = getrandom::Error::CUSTOM_START + 2;
+
+/// Fill `dest` from the canister-global generator.
+///
+/// This is the function registered with `getrandom` on
+/// `wasm32-unknown-unknown`; other targets keep their OS backend.
+pub fn fill_from_canister_rng(dest: &mut [u8]) -> Result<(), getrandom::Error> {
+    global::with_rng(|rng| rng.fill_bytes(dest)).map_err(|err| {
+        let code = match err {
+            Error::Busy => BUSY,
+            _ => UNSEEDED,
+        };
+        getrandom::Error::from(NonZeroU32::new(code).unwrap())
+    })
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+getrandom::register_custom_getrandom!(fill_from_canister_rng);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_until_seeded() {
+        let mut buf = [0u8; 16];
+        let err = fill_from_canister_rng(&mut buf).unwrap_err();
+        assert_eq!(err.code().get(), UNSEEDED);
+
+        global::seed_canister_rng([5; 32]);
+        fill_from_canister_rng(&mut buf).unwrap();
+        assert_ne!(buf, [0; 16]);
+
+        let mut expected = [0u8; 16];
+        global::seed_canister_rng([5; 32]);
+        global::with_rng(|rng| rng.fill_bytes(&mut expected)).unwrap();
+        assert_eq!(buf, expected);
+        global::take_canister_rng();
+    }
+}
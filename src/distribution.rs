@@ -1,17 +1,17 @@
 use std::iter;
 
-use crate::utils::Rng;
+use crate::core::RngCore;
 
 pub trait Distribution<T> {
-    ///generate a random value of 'T, in current stage using 'Rng' as the source of randomness.
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> T;
+    ///generate a random value of 'T, in current stage using 'RngCore' as the source of randomness.
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> T;
 
-    ///create an iterator that generates random values of 'T', using 'Rng' as 
+    ///create an iterator that generates random values of 'T', using 'RngCore' as 
     /// the source of randomness.
     /// 
     fn sample_iter<R>(self,rng: R) -> DistIter<Self, R, T>
     where
-        R: Rng,
+        R: RngCore,
         Self: Sized,
     {
         DistIter {
@@ -37,8 +37,8 @@ pub trait Distribution<T> {
     }
 }
 
-impl<'a, T, D:Distribution<T> + ?Sized> Distribution<T> for &'a D {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> T {
+impl<T, D:Distribution<T> + ?Sized> Distribution<T> for &D {
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> T {
         (*self).sample(rng)
     }
 }
@@ -53,7 +53,7 @@ pub struct DistIter<D,R, T> {
 impl<D, R, T> Iterator for DistIter<D, R, T>
 where 
     D: Distribution<T>,
-    R: Rng,
+    R: RngCore,
 {
     type Item = T;
     fn next(&mut self) -> Option<T> {
@@ -61,14 +61,14 @@ where
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (usize::min_value(), None)
+        (usize::MIN, None)
     }
 }
 
 impl<D, R, T> iter::FusedIterator for DistIter<D, R, T>
 where 
     D: Distribution<T>,
-    R: Rng,
+    R: RngCore,
 {
 
 }
@@ -85,7 +85,7 @@ where
     D: Distribution<T>,
     F: Fn(T) -> S,
 {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> S {
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> S {
         (self.func)(self.dist.sample(rng))
     }
 }
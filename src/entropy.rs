@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+use std::future::{self, Future};
+
+use crate::core::{Error, RngCore, SeedableRng};
+use crate::utils::Rand64;
+
+/// A source of seed material for the generators in this crate.
+///
+/// On the IC, randomness comes from the management canister and can only
+/// be obtained with an inter-canister call, so fetching a seed is async.
+pub trait EntropySource {
+    /// Fetch 32 bytes of fresh entropy.
+    fn fetch_seed(&mut self) -> impl Future<Output = Result<[u8; 32], Error>>;
+}
+
+impl<S: EntropySource + ?Sized> EntropySource for &mut S {
+    fn fetch_seed(&mut self) -> impl Future<Output = Result<[u8; 32], Error>> {
+        (**self).fetch_seed()
+    }
+}
+
+/// Create a generator seeded from `source`.
+pub async fn from_entropy<G, S>(source: &mut S) -> Result<G, Error>
+where
+    G: SeedableRng,
+    S: EntropySource + ?Sized,
+{
+    let bytes = source.fetch_seed().await?;
+    Ok(seed_from_bytes(bytes))
+}
+
+/// Create a generator from 32 bytes of entropy, whatever its seed length.
+///
+/// Shorter seeds get the 32 bytes XOR-folded into them, so no input byte is
+/// ignored; longer seeds repeat the input.
+pub fn seed_from_bytes<G: SeedableRng>(bytes: [u8; 32]) -> G {
+    let mut seed = G::Seed::default();
+    let dst = seed.as_mut();
+    if dst.len() <= bytes.len() {
+        for (i, b) in bytes.iter().enumerate() {
+            dst[i % dst.len()] ^= b;
+        }
+    } else {
+        for (i, d) in dst.iter_mut().enumerate() {
+            *d = bytes[i % bytes.len()];
+        }
+    }
+    G::from_seed(seed)
+}
+
+/// Entropy from the IC management canister's `raw_rand` method.
+///
+/// Each fetch is one inter-canister call, so it can only be used from update
+/// calls and `init`/`post_upgrade` timers, not from queries.
+#[cfg(feature = "ic")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IcEntropy;
+
+#[cfg(feature = "ic")]
+impl EntropySource for IcEntropy {
+    async fn fetch_seed(&mut self) -> Result<[u8; 32], Error> {
+        let bytes = ic_cdk::management_canister::raw_rand()
+            .await
+            .map_err(|_| Error::Unavailable)?;
+        bytes.try_into().map_err(|_| Error::Unavailable)
+    }
+}
+
+/// A deterministic, in-process entropy source for tests.
+///
+/// Seeds either come from a fixed list, after which fetching fails with
+/// `Error::Unavailable`, or from an endless stream derived from a `u64`.
+#[derive(Clone, Debug)]
+pub struct MockEntropy {
+    seeds: VecDeque<[u8; 32]>,
+    stream: Option<Rand64>,
+    fetches: usize,
+}
+
+impl MockEntropy {
+    /// An endless source whose seeds are derived from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seeds: VecDeque::new(),
+            stream: Some(Rand64::seed_from_u64(seed)),
+            fetches: 0,
+        }
+    }
+
+    /// A source returning `seeds` in order, then failing.
+    pub fn from_seeds<I: IntoIterator<Item = [u8; 32]>>(seeds: I) -> Self {
+        Self {
+            seeds: seeds.into_iter().collect(),
+            stream: None,
+            fetches: 0,
+        }
+    }
+
+    /// A source which always fails, like a canister without cycles.
+    pub fn unavailable() -> Self {
+        Self::from_seeds([])
+    }
+
+    /// The number of times `fetch_seed` has been called.
+    pub fn fetch_count(&self) -> usize {
+        self.fetches
+    }
+
+    fn next_seed(&mut self) -> Result<[u8; 32], Error> {
+        self.fetches += 1;
+        if let Some(seed) = self.seeds.pop_front() {
+            return Ok(seed);
+        }
+        match &mut self.stream {
+            Some(rng) => {
+                let mut seed = [0u8; 32];
+                rng.fill_bytes(&mut seed);
+                Ok(seed)
+            }
+            None => Err(Error::Unavailable),
+        }
+    }
+}
+
+impl EntropySource for MockEntropy {
+    fn fetch_seed(&mut self) -> impl Future<Output = Result<[u8; 32], Error>> {
+        future::ready(self.next_seed())
+    }
+}
+
+/// Drive a future that is expected to be ready without outside wakeups,
+/// like the ones returned by `MockEntropy`.
+#[cfg(test)]
+pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    let mut fut = pin!(fut);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+            return out;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Rand32;
+
+    #[test]
+    fn mock_seeds_generators() {
+        let mut source = MockEntropy::new(1);
+        let mut replay = MockEntropy::new(1);
+        let a: Rand64 = block_on(from_entropy(&mut source)).unwrap();
+        let b: Rand64 = block_on(from_entropy(&mut replay)).unwrap();
+        assert_eq!(a, b);
+        let c: Rand64 = block_on(from_entropy(&mut source)).unwrap();
+        assert_ne!(a, c);
+        assert_eq!(source.fetch_count(), 2);
+    }
+
+    #[test]
+    fn fixed_seeds_then_unavailable() {
+        let seed = [7u8; 32];
+        let mut source = MockEntropy::from_seeds([seed]);
+        let rng: Rand64 = block_on(from_entropy(&mut source)).unwrap();
+        assert_eq!(rng, Rand64::from_seed(seed));
+        let err = block_on(from_entropy::<Rand32, _>(&mut source)).unwrap_err();
+        assert_eq!(err, Error::Unavailable);
+    }
+
+    #[test]
+    fn short_seeds_use_every_byte() {
+        let mut bytes = [0u8; 32];
+        let base: Rand32 = seed_from_bytes(bytes);
+        bytes[31] = 1;
+        let changed: Rand32 = seed_from_bytes(bytes);
+        assert_ne!(base, changed);
+    }
+}
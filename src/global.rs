@@ -0,0 +1,195 @@
+use std::cell::RefCell;
+use std::time::Duration;
+
+use crate::chacha::ChaCha20Rng;
+use crate::core::Error;
+use crate::entropy::{self, EntropySource};
+
+/// The generator type behind the canister-global RNG.
+///
+/// It is cryptographically secure, since other crates reach it through
+/// `getrandom` for keys and nonces: unlike PCG, its state cannot be
+/// recovered from its outputs.
+pub type CanisterRng = ChaCha20Rng;
+
+thread_local! {
+    static CANISTER_RNG: RefCell<Option<CanisterRng>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with the canister-global generator.
+///
+/// Returns `Error::Unseeded` if the generator has not been seeded yet with
+/// `init_canister_rng`, `seed_canister_rng` or `set_canister_rng`.
+///
+/// This works in query calls too, but state changes made by a query are
+/// discarded, so consecutive queries see the same values until an update
+/// call advances the generator.
+///
+/// Calling `with_rng` again from inside `f`, for example through a crate
+/// that uses `getrandom`, returns `Error::Busy`. The other functions of
+/// this module panic when called from inside `f`.
+pub fn with_rng<T, F>(f: F) -> Result<T, Error>
+where
+    F: FnOnce(&mut CanisterRng) -> T,
+{
+    CANISTER_RNG.with(|cell| {
+        let mut rng = cell.try_borrow_mut().map_err(|_| Error::Busy)?;
+        match rng.as_mut() {
+            Some(rng) => Ok(f(rng)),
+            None => Err(Error::Unseeded),
+        }
+    })
+}
+
+/// Whether the canister-global generator has been seeded.
+pub fn is_seeded() -> bool {
+    CANISTER_RNG.with(|cell| cell.borrow().is_some())
+}
+
+/// Seed the canister-global generator from `source`, replacing any
+/// previous generator.
+pub async fn init_canister_rng<S>(source: &mut S) -> Result<(), Error>
+where
+    S: EntropySource + ?Sized,
+{
+    let bytes = source.fetch_seed().await?;
+    seed_canister_rng(bytes);
+    Ok(())
+}
+
+/// Seed the canister-global generator from `source` unless it is already
+/// seeded.
+///
+/// Update methods can call this before `with_rng` so that the first caller
+/// after install or upgrade pays for the `raw_rand` call.
+pub async fn ensure_seeded<S>(source: &mut S) -> Result<(), Error>
+where
+    S: EntropySource + ?Sized,
+{
+    if is_seeded() {
+        return Ok(());
+    }
+    init_canister_rng(source).await
+}
+
+/// Seed the canister-global generator from 32 bytes of entropy that were
+/// already fetched.
+pub fn seed_canister_rng(bytes: [u8; 32]) {
+    set_canister_rng(entropy::seed_from_bytes::<CanisterRng>(bytes));
+}
+
+/// Replace the canister-global generator, e.g. with one restored from
+/// stable memory.
+pub fn set_canister_rng(rng: CanisterRng) {
+    CANISTER_RNG.with(|cell| *cell.borrow_mut() = Some(rng));
+}
+
+/// Remove the canister-global generator, returning it if it was seeded.
+///
+/// Later calls to `with_rng` fail with `Error::Unseeded` until it is
+/// seeded again.
+pub fn take_canister_rng() -> Option<CanisterRng> {
+    CANISTER_RNG.with(|cell| cell.borrow_mut().take())
+}
+
+/// Reseed the canister-global generator from `raw_rand` as soon as
+/// possible.
+///
+/// Inter-canister calls cannot be made from `init` or `post_upgrade`, so
+/// call this from both hooks: it schedules the reseed on a zero-delay timer.
+/// Messages handled before the timer fires see `Error::Unseeded` from
+/// `with_rng`; update methods can `ensure_seeded` first to avoid that.
+///
+/// If `raw_rand` fails, the reseed is retried with an exponential backoff,
+/// from `RESEED_RETRY_MIN` up to `RESEED_RETRY_MAX` between attempts, and
+/// each failure is logged.
+#[cfg(feature = "ic")]
+pub fn schedule_reseed() {
+    reseed_after(Duration::ZERO);
+}
+
+#[cfg(feature = "ic")]
+fn reseed_after(delay: Duration) {
+    ic_cdk_timers::set_timer(delay, async move {
+        if let Err(err) = init_canister_rng(&mut entropy::IcEntropy).await {
+            let next = next_retry_delay(delay);
+            ic_cdk::println!(
+                "reseeding the canister RNG failed: {}; retrying in {:?}",
+                err,
+                next
+            );
+            reseed_after(next);
+        }
+    });
+}
+
+/// The delay before the first retry of a failed reseed.
+pub const RESEED_RETRY_MIN: Duration = Duration::from_secs(1);
+
+/// The longest delay between two retries of a failed reseed.
+pub const RESEED_RETRY_MAX: Duration = Duration::from_secs(600);
+
+/// The delay before the next reseed attempt, after one that was scheduled
+/// `delay` after the previous failure failed as well.
+#[cfg(any(feature = "ic", test))]
+fn next_retry_delay(delay: Duration) -> Duration {
+    delay.saturating_mul(2).clamp(RESEED_RETRY_MIN, RESEED_RETRY_MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::{block_on, MockEntropy};
+    use crate::RngCore;
+
+    #[test]
+    fn unseeded_rng_is_an_error() {
+        assert!(!is_seeded());
+        assert_eq!(with_rng(|rng| rng.next_u32()), Err(Error::Unseeded));
+    }
+
+    #[test]
+    fn lazy_init_only_fetches_once() {
+        let mut source = MockEntropy::new(3);
+        block_on(ensure_seeded(&mut source)).unwrap();
+        let first = with_rng(|rng| rng.next_u64()).unwrap();
+        block_on(ensure_seeded(&mut source)).unwrap();
+        assert_eq!(source.fetch_count(), 1);
+        assert_ne!(with_rng(|rng| rng.next_u64()).unwrap(), first);
+    }
+
+    #[test]
+    fn reinit_replaces_the_stream() {
+        let seed = [9u8; 32];
+        let mut source = MockEntropy::from_seeds([seed, seed]);
+        block_on(init_canister_rng(&mut source)).unwrap();
+        let a = with_rng(|rng| rng.next_u64()).unwrap();
+        block_on(init_canister_rng(&mut source)).unwrap();
+        assert_eq!(with_rng(|rng| rng.next_u64()).unwrap(), a);
+
+        assert_eq!(block_on(init_canister_rng(&mut source)), Err(Error::Unavailable));
+        assert!(is_seeded());
+        assert!(take_canister_rng().is_some());
+        assert!(!is_seeded());
+    }
+
+    #[test]
+    fn nested_use_is_an_error() {
+        seed_canister_rng([1; 32]);
+        let inner = with_rng(|_| with_rng(|rng| rng.next_u32())).unwrap();
+        assert_eq!(inner, Err(Error::Busy));
+        assert!(with_rng(|rng| rng.next_u32()).is_ok());
+        take_canister_rng();
+    }
+
+    #[test]
+    fn retries_back_off() {
+        let mut delay = Duration::ZERO;
+        let mut delays = Vec::new();
+        for _ in 0..12 {
+            delay = next_retry_delay(delay);
+            delays.push(delay.as_secs());
+        }
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 64, 128, 256, 512, 600, 600]);
+    }
+}
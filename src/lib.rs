@@ -1,13 +1,35 @@
-use utils::Rng;
+// NaN-aware comparisons such as `!(low < high)` are intentional.
+#![allow(clippy::neg_cmp_op_on_partial_ord)]
 
 pub mod utils;
 pub mod distribution;
 pub mod core;
 pub mod uniform;
-// pub mod slice;
+pub mod slice;
 pub mod other;
 pub mod bernouilli;
 pub mod weight_index;
+pub mod rng;
+pub mod entropy;
+pub mod global;
+pub mod stable;
+pub mod hash;
+pub mod kdf;
+pub mod commit_reveal;
+pub mod transcript;
+pub mod block;
+pub mod chacha;
+pub mod reseeding;
+pub mod pcg;
+pub mod xoshiro;
+pub mod small;
+#[cfg(feature = "getrandom")]
+pub mod custom_getrandom;
+#[cfg(unix)]
+pub mod os;
+
+pub use crate::core::{Error, RngCore, SeedableRng};
+pub use crate::rng::RngExt;
 
 pub fn gen_f32() -> f32 {
     let seed = 12345u64;
@@ -19,10 +41,347 @@ pub fn gen_f32() -> f32 {
 mod tests {
     use super::*;
 
-    
     #[test]
     fn gen() {
         let result = gen_f32();
         println!("random f32 {:?}", result);
     }
+
+    #[test]
+    fn core_output_is_consistent() {
+        let mut a = utils::Rand32::new(7);
+        let mut b = a;
+        let lo = a.next_u32() as u64;
+        let hi = a.next_u32() as u64;
+        assert_eq!(b.next_u64(), (hi << 32) | lo);
+
+        let mut a = utils::Rand64::new(7);
+        let mut b = a;
+        let mut bytes = [0u8; 12];
+        a.fill_bytes(&mut bytes);
+        assert_eq!(bytes[..8], b.next_u64().to_le_bytes());
+        assert_eq!(bytes[8..], b.next_u64().to_le_bytes()[..4]);
+    }
+
+    #[test]
+    fn distributions_accept_either_generator() {
+        use distribution::Distribution;
+
+        let always = bernouilli::Bernoulli::new(1.0).unwrap();
+        let never = bernouilli::Bernoulli::new(0.0).unwrap();
+        let mut rng32 = utils::Rand32::new(1);
+        let mut rng64 = utils::Rand64::new(1);
+        for _ in 0..100 {
+            assert!(always.sample(&mut rng32));
+            assert!(!never.sample(&mut rng64));
+        }
+
+        let half = bernouilli::Bernoulli::new(0.5).unwrap();
+        let hits = half.sample_iter(&mut rng64).take(1000).filter(|&b| b).count();
+        assert!((400..600).contains(&hits));
+    }
+
+    #[cfg(feature = "candid")]
+    #[test]
+    fn candid_round_trip() {
+        use bernouilli::Bernoulli;
+        use distribution::Distribution;
+        use uniform::Uniform;
+        use weight_index::WeightIndex;
+
+        fn round_trip<T>(value: &T) -> T
+        where
+            T: candid::CandidType + for<'de> serde::Deserialize<'de>,
+        {
+            let bytes = candid::encode_one(value).unwrap();
+            candid::decode_one(&bytes).unwrap()
+        }
+
+        let mut rng32 = utils::Rand32::seed_from_u64(1);
+        let _ = rng32.next_u32();
+        assert_eq!(round_trip(&rng32), rng32);
+        let mut rng64 = utils::Rand64::seed_from_u64(1);
+        let _ = rng64.next_u64();
+        assert_eq!(round_trip(&rng64), rng64);
+        let mut chacha = chacha::ChaCha20Rng::seed_from_u64(1);
+        let _ = chacha.next_u32();
+        assert_eq!(round_trip(&chacha), chacha);
+        let mut dxsm = pcg::Pcg64Dxsm::seed_from_u64(1);
+        let _ = dxsm.next_u64();
+        assert_eq!(round_trip(&dxsm), dxsm);
+        let mut xoshiro = xoshiro::Xoshiro256PlusPlus::seed_from_u64(1);
+        let _ = xoshiro.next_u64();
+        assert_eq!(round_trip(&xoshiro), xoshiro);
+
+        let b = Bernoulli::from_ratio(1, 3).unwrap();
+        assert_eq!(round_trip(&b), b);
+
+        let u = Uniform::new(-5i128, 1 << 100).unwrap();
+        assert_eq!(round_trip(&u), u);
+        let u = Uniform::new_inclusive(0.5f64, 2.0).unwrap();
+        assert_eq!(round_trip(&u), u);
+        let u = Uniform::new_inclusive(1usize, 6).unwrap();
+        assert_eq!(round_trip(&u), u);
+
+        let c = Uniform::new('a', 'z').unwrap();
+        let decoded = round_trip(&c);
+        let mut a = rng64;
+        let mut b = rng64;
+        for _ in 0..10 {
+            assert_eq!(c.sample(&mut a), decoded.sample(&mut b));
+        }
+
+        let w = WeightIndex::new([1u64, 0, 7, 2]).unwrap();
+        assert_eq!(round_trip(&w), w);
+        let w = WeightIndex::new([0.5f32, 1.5]).unwrap();
+        assert_eq!(round_trip(&w), w);
+    }
+
+    #[cfg(feature = "candid")]
+    #[test]
+    fn candid_rejects_invalid_char_ranges() {
+        use uniform::Uniform;
+
+        // a `Uniform<u32>` has the same encoding as the sampler inside a
+        // `Uniform<char>`, which samples compressed scalar values
+        #[derive(candid::CandidType)]
+        struct Record {
+            sampler: Uniform<u32>,
+        }
+
+        fn decode(sampler: Uniform<u32>) -> Result<Uniform<char>, candid::Error> {
+            let bytes = candid::encode_one(Record { sampler }).unwrap();
+            candid::decode_one(&bytes)
+        }
+
+        let max = 0x10FFFF - 0x800;
+        assert!(decode(Uniform::new_inclusive(0, max).unwrap()).is_ok());
+        // one past the last scalar value, the full `u32` range, and a
+        // range entirely past it
+        assert!(decode(Uniform::new_inclusive(0, max + 1).unwrap()).is_err());
+        assert!(decode(Uniform::new_inclusive(0, u32::MAX).unwrap()).is_err());
+        assert!(decode(Uniform::new_inclusive(u32::MAX - 1, u32::MAX).unwrap()).is_err());
+    }
+
+    #[cfg(feature = "candid")]
+    #[test]
+    fn candid_rejects_invalid_states() {
+        use uniform::{Uniform, UniformFloat, UniformInt, UniformSampler};
+        use weight_index::WeightIndex;
+
+        fn decode<T, D>(value: T) -> Result<D, candid::Error>
+        where
+            T: candid::CandidType,
+            D: candid::CandidType + for<'de> serde::Deserialize<'de>,
+        {
+            candid::decode_one(&candid::encode_one(value).unwrap())
+        }
+
+        #[derive(candid::CandidType)]
+        struct IntRecord {
+            low: u64,
+            range: u64,
+            z: u64,
+        }
+
+        // a `z` leaving no accepted zone is recomputed, so sampling ends
+        let tampered = IntRecord { low: 0, range: 3, z: u64::MAX };
+        let u: UniformInt<u64> = decode(tampered).unwrap();
+        assert_eq!(u, UniformInt::<u64>::new_inclusive(0, 2).unwrap());
+        assert!(u.sample(&mut utils::Rand64::new(1)) <= 2);
+        // a range wrapping past `u64::MAX`, and the full range not starting
+        // at zero
+        let tampered = IntRecord { low: 5, range: u64::MAX, z: 0 };
+        assert!(decode::<_, UniformInt<u64>>(tampered).is_err());
+        let tampered = IntRecord { low: 5, range: 0, z: 0 };
+        assert!(decode::<_, UniformInt<u64>>(tampered).is_err());
+        let full = IntRecord { low: 0, range: 0, z: 0 };
+        assert!(decode::<_, UniformInt<u64>>(full).is_ok());
+
+        #[derive(candid::CandidType)]
+        struct FloatRecord {
+            low: f64,
+            scale: f64,
+        }
+
+        for scale in [-1.0, f64::NAN, f64::INFINITY, f64::MAX] {
+            let tampered = FloatRecord { low: f64::MAX, scale };
+            assert!(decode::<_, UniformFloat<f64>>(tampered).is_err(), "{}", scale);
+        }
+
+        #[derive(candid::CandidType)]
+        struct WeightRecord {
+            cumulative_weights: Vec<u32>,
+            total_weight: u32,
+            weight_distribution: Uniform<u32>,
+        }
+
+        // the sampler is rebuilt from the total
+        let w = WeightIndex::new([1u32, 2, 3]).unwrap();
+        let tampered = WeightRecord {
+            cumulative_weights: vec![1, 3],
+            total_weight: 6,
+            weight_distribution: Uniform::new(0, 1000).unwrap(),
+        };
+        assert_eq!(decode::<_, WeightIndex<u32>>(tampered).unwrap(), w);
+        let tampered = WeightRecord {
+            cumulative_weights: vec![3, 1],
+            total_weight: 6,
+            weight_distribution: Uniform::new(0, 6).unwrap(),
+        };
+        assert!(decode::<_, WeightIndex<u32>>(tampered).is_err());
+        let tampered = WeightRecord {
+            cumulative_weights: vec![1, 7],
+            total_weight: 6,
+            weight_distribution: Uniform::new(0, 6).unwrap(),
+        };
+        assert!(decode::<_, WeightIndex<u32>>(tampered).is_err());
+        let tampered = WeightRecord {
+            cumulative_weights: vec![0],
+            total_weight: 0,
+            weight_distribution: Uniform::new(0, 6).unwrap(),
+        };
+        assert!(decode::<_, WeightIndex<u32>>(tampered).is_err());
+
+        #[derive(candid::CandidType)]
+        struct PcgRecord<T> {
+            state: T,
+            inc: T,
+        }
+
+        assert!(decode::<_, utils::Rand32>(PcgRecord { state: 1u64, inc: 3u64 }).is_ok());
+        assert!(decode::<_, utils::Rand32>(PcgRecord { state: 1u64, inc: 2u64 }).is_err());
+        assert!(decode::<_, utils::Rand64>(PcgRecord { state: 1u128, inc: 3u128 }).is_ok());
+        assert!(decode::<_, utils::Rand64>(PcgRecord { state: 1u128, inc: 2u128 }).is_err());
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn serde_rejects_invalid_char_ranges() {
+        use uniform::Uniform;
+
+        let c = Uniform::new_inclusive('a', 'z').unwrap();
+        let json = serde_json::to_string(&c).unwrap();
+        assert_eq!(json, r#"{"sampler":{"low":97,"range":26,"z":22}}"#);
+
+        // ranges reaching past the last compressed scalar value, or the
+        // whole `u32` range, must not decode
+        let tampered = [
+            r#"{"sampler":{"low":97,"range":1112064,"z":0}}"#,
+            r#"{"sampler":{"low":0,"range":1112065,"z":0}}"#,
+            r#"{"sampler":{"low":0,"range":0,"z":0}}"#,
+            r#"{"sampler":{"low":4294967295,"range":2,"z":0}}"#,
+        ];
+        for json in tampered {
+            assert!(serde_json::from_str::<Uniform<char>>(json).is_err(), "{}", json);
+        }
+        let json = r#"{"sampler":{"low":0,"range":1112064,"z":0}}"#;
+        assert!(serde_json::from_str::<Uniform<char>>(json).is_ok());
+
+        // bincode has no field names, so a `Uniform<u32>` reads as one
+        let bytes = bincode::serialize(&Uniform::new_inclusive(0u32, u32::MAX).unwrap()).unwrap();
+        assert!(bincode::deserialize::<Uniform<char>>(&bytes).is_err());
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn serde_rejects_invalid_states() {
+        use uniform::{UniformFloat, UniformInt, UniformSampler};
+        use weight_index::WeightIndex;
+
+        // a `z` leaving no accepted zone is recomputed, so sampling ends
+        let json = r#"{"low":0,"range":3,"z":18446744073709551615}"#;
+        let u: UniformInt<u64> = serde_json::from_str(json).unwrap();
+        assert_eq!(u, UniformInt::<u64>::new_inclusive(0, 2).unwrap());
+        assert!(u.sample(&mut utils::Rand64::new(1)) <= 2);
+        let tampered = [
+            r#"{"low":5,"range":18446744073709551615,"z":0}"#,
+            r#"{"low":5,"range":0,"z":0}"#,
+        ];
+        for json in tampered {
+            assert!(serde_json::from_str::<UniformInt<u64>>(json).is_err(), "{}", json);
+        }
+
+        let json = r#"{"low":1.0,"scale":-1.0}"#;
+        assert!(serde_json::from_str::<UniformFloat<f64>>(json).is_err());
+        let bytes = bincode::serialize(&(f64::MAX, f64::MAX)).unwrap();
+        assert!(bincode::deserialize::<UniformFloat<f64>>(&bytes).is_err());
+
+        let json = r#"{"cumulative_weights":[3,1],"total_weight":6,"weight_distribution":{"low":0,"range":6,"z":4}}"#;
+        assert!(serde_json::from_str::<WeightIndex<u32>>(json).is_err());
+
+        let rng = utils::Rand32::new_inc(1, 2);
+        let json = serde_json::to_string(&rng).unwrap();
+        assert_eq!(serde_json::from_str::<utils::Rand32>(&json).unwrap(), rng);
+        assert!(serde_json::from_str::<utils::Rand32>(r#"{"state":1,"inc":2}"#).is_err());
+        let rng = utils::Rand64::new_inc(1, 2);
+        let json = serde_json::to_string(&rng).unwrap();
+        assert_eq!(serde_json::from_str::<utils::Rand64>(&json).unwrap(), rng);
+        assert!(serde_json::from_str::<utils::Rand64>(r#"{"state":1,"inc":2}"#).is_err());
+        let bytes = bincode::serialize(&(1u128, 2u128)).unwrap();
+        assert!(bincode::deserialize::<utils::Rand64>(&bytes).is_err());
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn serde_round_trip() {
+        use bernouilli::Bernoulli;
+        use distribution::Distribution;
+        use uniform::Uniform;
+        use weight_index::WeightIndex;
+
+        fn round_trip<T>(value: &T) -> T
+        where
+            T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+        {
+            let json = serde_json::to_string(value).unwrap();
+            let from_json: T = serde_json::from_str(&json).unwrap();
+            let bin = bincode::serialize(value).unwrap();
+            let from_bin: T = bincode::deserialize(&bin).unwrap();
+            assert_eq!(from_json, from_bin);
+            from_json
+        }
+
+        let mut rng32 = utils::Rand32::seed_from_u64(2);
+        let _ = rng32.next_u32();
+        assert_eq!(round_trip(&rng32), rng32);
+        let mut rng64 = utils::Rand64::seed_from_u64(2);
+        let _ = rng64.next_u64();
+        assert_eq!(round_trip(&rng64), rng64);
+        let mut chacha = chacha::ChaCha8Rng::seed_from_u64(2);
+        let _ = chacha.next_u32();
+        assert_eq!(round_trip(&chacha), chacha);
+        let mut dxsm = pcg::Pcg64Dxsm::seed_from_u64(2);
+        let _ = dxsm.next_u64();
+        assert_eq!(round_trip(&dxsm), dxsm);
+        let mut xoshiro = xoshiro::Xoshiro256PlusPlus::seed_from_u64(2);
+        let _ = xoshiro.next_u64();
+        assert_eq!(round_trip(&xoshiro), xoshiro);
+
+        let b = Bernoulli::new(0.25).unwrap();
+        assert_eq!(round_trip(&b), b);
+
+        let u = Uniform::new(-5i128, 1 << 100).unwrap();
+        assert_eq!(round_trip(&u), u);
+        let u = Uniform::new(0.5f32, 2.0).unwrap();
+        assert_eq!(round_trip(&u), u);
+
+        let w = WeightIndex::new([1u32, 0, 7, 2]).unwrap();
+        assert_eq!(round_trip(&w), w);
+        let w = WeightIndex::new([0.5f64, 1.5]).unwrap();
+        assert_eq!(round_trip(&w), w);
+
+        // `UniformChar` has no `PartialEq`; compare what it samples instead
+        let c = Uniform::new_inclusive('\u{1F600}', '\u{1F64F}').unwrap();
+        let json = serde_json::to_string(&c).unwrap();
+        let decoded: Uniform<char> = serde_json::from_str(&json).unwrap();
+        let bin = bincode::serialize(&c).unwrap();
+        let from_bin: Uniform<char> = bincode::deserialize(&bin).unwrap();
+        let (mut a, mut b, mut d) = (rng64, rng64, rng64);
+        for _ in 0..10 {
+            let x = c.sample(&mut a);
+            assert_eq!(decoded.sample(&mut b), x);
+            assert_eq!(from_bin.sample(&mut d), x);
+        }
+    }
 }
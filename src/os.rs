@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::sync::OnceLock;
+
+use crate::core::{Error, RngCore};
+
+/// A generator reading from the operating system's random source,
+/// `/dev/urandom`.
+///
+/// This is for native builds such as tests and off-chain tools; canisters
+/// have no OS and get entropy from `raw_rand` instead. It is slow compared
+/// to the seeded generators, so the usual pattern is to seed one of those
+/// with `SeedableRng::from_rng(OsRng)`.
+///
+/// `try_fill_bytes` reports failures as `Error::Os`; the other `RngCore`
+/// methods panic on failure.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OsRng;
+
+/// The device, opened on first use and kept open afterwards.
+static URANDOM: OnceLock<File> = OnceLock::new();
+
+fn urandom() -> io::Result<&'static File> {
+    if let Some(file) = URANDOM.get() {
+        return Ok(file);
+    }
+    let file = File::open("/dev/urandom")?;
+    // another thread may have won the race; its handle is as good as ours
+    Ok(URANDOM.get_or_init(|| file))
+}
+
+fn os_error(err: io::Error) -> Error {
+    match err.raw_os_error() {
+        Some(code) => Error::Os(code),
+        None => Error::Unavailable,
+    }
+}
+
+impl RngCore for OsRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0u8; 4];
+        self.fill_bytes(&mut buf);
+        u32::from_le_bytes(buf)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut buf = [0u8; 8];
+        self.fill_bytes(&mut buf);
+        u64::from_le_bytes(buf)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(err) = self.try_fill_bytes(dest) {
+            panic!("OsRng failed: {}", err);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        if dest.is_empty() {
+            return Ok(());
+        }
+        // `&File` implements `Read`, so the shared handle needs no lock
+        let mut file = urandom().map_err(os_error)?;
+        file.read_exact(dest).map_err(os_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::SeedableRng;
+    use crate::utils::Rand64;
+
+    #[test]
+    fn fills_and_seeds() {
+        let mut buf = [0u8; 64];
+        OsRng.try_fill_bytes(&mut buf).unwrap();
+        assert_ne!(buf, [0; 64]);
+        assert_ne!(OsRng.next_u64(), OsRng.next_u64());
+
+        let a = Rand64::from_rng(OsRng).unwrap();
+        let b = Rand64::from_rng(OsRng).unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn io_errors_keep_the_os_code() {
+        let err = os_error(io::Error::from_raw_os_error(5));
+        assert_eq!(err, Error::Os(5));
+        assert_eq!(err.to_string(), "the OS random source failed (os error 5)");
+        assert_eq!(
+            os_error(io::ErrorKind::UnexpectedEof.into()),
+            Error::Unavailable
+        );
+    }
+}
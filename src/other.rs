@@ -1,40 +1,43 @@
-use core::num::Wrapping;
-use std::mem::{MaybeUninit, self};
+use core::num::{
+    NonZeroU128, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize, Wrapping,
+};
 
 use crate::distribution::{Distribution, Standard};
-use crate::uniform::{Uniform, UniformFloat};
-use crate::utils::Rng;
+use crate::uniform::Uniform;
+use crate::core::RngCore;
+use crate::rng::RngExt;
 
 #[cfg(feature="serde1")]
-use serde::{Serialize, Deserialize};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature="serde1", derive(Serialize, Deserialize))]
 pub struct Alphanumeric;
 
-// impl Distribution<char> for Standard {
-//     #[inline]
-//     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> char {
-//         // A valid `char` is either in the interval `[0, 0xD800)` or
-//         // `(0xDFFF, 0x11_0000)`. All `char`s must therefore be in
-//         // `[0, 0x11_0000)` but not in the "gap" `[0xD800, 0xDFFF]` which is
-//         // reserved for surrogates. This is the size of that gap.
-//         const GAP_SIZE: u32 = 0xDFFF - 0xD800 + 1;
-
-//         // Uniform::new(0, 0x11_0000 - GAP_SIZE) can also be used, but it
-//         // seemed slower.
-//         let range = Uniform::new(GAP_SIZE, 0x11_0000).unwrap();
-
-//         let mut n = range.sample(rng);
-//         if n <= 0xDFFF {
-//             n -= GAP_SIZE;
-//         }
-//         unsafe { char::from_u32_unchecked(n) }
-//     }
-// }
+impl Distribution<char> for Standard {
+    #[inline]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> char {
+        // A valid `char` is either in the interval `[0, 0xD800)` or
+        // `(0xDFFF, 0x11_0000)`. All `char`s must therefore be in
+        // `[0, 0x11_0000)` but not in the "gap" `[0xD800, 0xDFFF]` which is
+        // reserved for surrogates. This is the size of that gap.
+        const GAP_SIZE: u32 = 0xDFFF - 0xD800 + 1;
+
+        // Uniform::new(0, 0x11_0000 - GAP_SIZE) can also be used, but it
+        // seemed slower.
+        let range = Uniform::new(GAP_SIZE, 0x11_0000).unwrap();
+
+        let mut n = range.sample(rng);
+        if n <= 0xDFFF {
+            n -= GAP_SIZE;
+        }
+        // safety: `n` is in `[0, 0xD800)` or `(0xDFFF, 0x11_0000)`
+        unsafe { char::from_u32_unchecked(n) }
+    }
+}
 
 impl Distribution<u8> for Alphanumeric {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u8 {
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u8 {
         const RANGE: u32 = 26 + 26 + 10;
         const GEN_ASCII_STR_CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
                 abcdefghijklmnopqrstuvwxyz\
@@ -44,7 +47,7 @@ impl Distribution<u8> for Alphanumeric {
         // rejection sampling. We do not use a bitmask, because for small RNGs
         // the most significant bits are usually of higher quality.
         loop {
-            let var = rng.rand_u32() >> (32 - 6);
+            let var = rng.next_u32() >> (32 - 6);
             if var < RANGE {
                 return GEN_ASCII_STR_CHARSET[var as usize];
             }
@@ -52,88 +55,249 @@ impl Distribution<u8> for Alphanumeric {
     }
 }
 
+impl Distribution<u8> for Standard {
+    #[inline]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u8 {
+        rng.next_u32() as u8
+    }
+}
+
+impl Distribution<u16> for Standard {
+    #[inline]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u16 {
+        rng.next_u32() as u16
+    }
+}
+
+impl Distribution<u32> for Standard {
+    #[inline]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u32 {
+        rng.next_u32()
+    }
+}
+
+impl Distribution<u64> for Standard {
+    #[inline]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u64 {
+        rng.next_u64()
+    }
+}
+
+impl Distribution<u128> for Standard {
+    #[inline]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> u128 {
+        // use LE; we explicitly generate one value before the next
+        let x = u128::from(rng.next_u64());
+        let y = u128::from(rng.next_u64());
+        (y << 64) | x
+    }
+}
+
+impl Distribution<usize> for Standard {
+    #[inline]
+    #[cfg(target_pointer_width = "32")]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> usize {
+        rng.next_u32() as usize
+    }
+
+    #[inline]
+    #[cfg(target_pointer_width = "64")]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> usize {
+        rng.next_u64() as usize
+    }
+}
+
+macro_rules! signed_int_impl {
+    ($ty: ty, $unsigned: ty) => {
+        impl Distribution<$ty> for Standard {
+            #[inline]
+            fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> $ty {
+                rng.gen::<$unsigned>() as $ty
+            }
+        }
+    };
+}
+
+signed_int_impl! { i8, u8 }
+signed_int_impl! { i16, u16 }
+signed_int_impl! { i32, u32 }
+signed_int_impl! { i64, u64 }
+signed_int_impl! { i128, u128 }
+signed_int_impl! { isize, usize }
+
+macro_rules! nonzero_int_impl {
+    ($ty: ty, $int: ty) => {
+        impl Distribution<$ty> for Standard {
+            #[inline]
+            fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> $ty {
+                // rejecting zero keeps the other values uniform
+                loop {
+                    if let Some(nz) = <$ty>::new(rng.gen::<$int>()) {
+                        break nz;
+                    }
+                }
+            }
+        }
+    };
+}
+
+nonzero_int_impl! { NonZeroU8, u8 }
+nonzero_int_impl! { NonZeroU16, u16 }
+nonzero_int_impl! { NonZeroU32, u32 }
+nonzero_int_impl! { NonZeroU64, u64 }
+nonzero_int_impl! { NonZeroU128, u128 }
+nonzero_int_impl! { NonZeroUsize, usize }
+
+macro_rules! float_impl {
+    ($ty: ty, $uty: ident, $fraction_bits: expr) => {
+        /// Samples from `[0, 1)` with the full precision of the mantissa.
+        impl Distribution<$ty> for Standard {
+            #[inline]
+            fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> $ty {
+                const BITS: u32 = $uty::BITS;
+                const PRECISION: u32 = $fraction_bits + 1;
+                const SCALE: $ty = 1.0 / (((1 as $uty) << PRECISION) as $ty);
+
+                let value: $uty = rng.gen();
+                SCALE * (value >> (BITS - PRECISION)) as $ty
+            }
+        }
+    };
+}
+
+float_impl! { f32, u32, 23 }
+float_impl! { f64, u64, 52 }
+
 impl Distribution<bool> for Standard {
     #[inline]
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> bool {
-        rng.rand_i32() < 0
-    }
-}
-
-// macro_rules! tuple_impl {
-//     ($($tyvar: ident), *) => {
-//         //the trailing commas are for the 1 tuple
-//         impl<$( $tyvar), *>
-//             Distribution<($( $tyvar ),* , )>
-//             for Standard
-//             where $( Standard: Distribution<$tyvar> ),*
-//         {
-//             #[inline]
-//             fn sample<R: Rng + ?Sized>(&self, _rng: &mut R) -> ( $( $tyvar ),* , ) {
-//                 $(
-//                     _rng.gen::<$tyvar>()
-//                 ),*
-//                 ,
-//             }
-//         }
-//     }
-// }
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> bool {
+        (rng.next_u32() as i32) < 0
+    }
+}
+
+macro_rules! tuple_impl {
+    ($($tyvar: ident), *) => {
+        //the trailing commas are for the 1 tuple
+        impl<$( $tyvar), *>
+            Distribution<($( $tyvar ),* , )>
+            for Standard
+            where $( Standard: Distribution<$tyvar> ),*
+        {
+            #[inline]
+            fn sample<R: RngCore + ?Sized>(&self, _rng: &mut R) -> ( $( $tyvar ),* , ) {
+                (
+                    // use the $tyvar's to get the appropriate number of
+                    // repeats (they're not actually needed)
+                    $(
+                        _rng.gen::<$tyvar>()
+                    ),*
+                    ,
+                )
+            }
+        }
+    }
+}
 
 impl Distribution<()> for Standard {
     #[allow(clippy::unused_unit)]
     #[inline]
-    fn sample<R: Rng + ?Sized>(&self, _: &mut R) -> () {
+    fn sample<R: RngCore + ?Sized>(&self, _: &mut R) -> () {
         ()
     }
 }
 
-// tuple_impl! {A}
-// tuple_impl! {A, B}
-// tuple_impl! {A, B, C}
-// tuple_impl! {A, B, C, D}
-// tuple_impl! {A, B, C, D, E}
-// tuple_impl! {A, B, C, D, E, F}
-// tuple_impl! {A, B, C, D, E, F, G}
-// tuple_impl! {A, B, C, D, E, F, G, H}
-// tuple_impl! {A, B, C, D, E, F, G, H, I}
-// tuple_impl! {A, B, C, D, E, F, G, H, I, J}
-// tuple_impl! {A, B, C, D, E, F, G, H, I, J, K}
-// tuple_impl! {A, B, C, D, E, F, G, H, I, J, K, L}
-
-// impl<T, const N: usize> Distribution<[T; N]> for Standard
-// where Standard: Distribution<T>
-// {
-//     #[inline]
-//     fn sample<R: Rng + ?Sized>(&self, _rng: &mut R) -> [T; N] {
-//         let mut buff: [MaybeUninit<T>; N] = unsafe {
-//             MaybeUninit::uninit().assume_init()
-//         };
-
-//         for elem in &mut buff {
-//             *elem = MaybeUninit::new(_rng.gen());
-//         }
-//         unsafe { mem::transmute_copy::<_, _>(&buff)}
-//     }
-
-// }
-
-// impl<T> Distribution<Option<T>> for Standard
-// where Standard: Distribution<T>
-// {
-//     #[inline]
-//     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<T> {
-//         if rng.gen::<bool>() {
-//             Some(rng.gen())
-//         } else {
-//             None
-//         }
-//     }
-// }
-
-// impl<T> Distribution<Wrapping<T>> for Standard
-// where Standard: Distribution<T>
-// {
-//     #[inline]
-//     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Wrapping<T> {
-//         Wrapping(rng.gen())
-//     }
-// }
\ No newline at end of file
+tuple_impl! {A}
+tuple_impl! {A, B}
+tuple_impl! {A, B, C}
+tuple_impl! {A, B, C, D}
+tuple_impl! {A, B, C, D, E}
+tuple_impl! {A, B, C, D, E, F}
+tuple_impl! {A, B, C, D, E, F, G}
+tuple_impl! {A, B, C, D, E, F, G, H}
+tuple_impl! {A, B, C, D, E, F, G, H, I}
+tuple_impl! {A, B, C, D, E, F, G, H, I, J}
+tuple_impl! {A, B, C, D, E, F, G, H, I, J, K}
+tuple_impl! {A, B, C, D, E, F, G, H, I, J, K, L}
+
+impl<T, const N: usize> Distribution<[T; N]> for Standard
+where Standard: Distribution<T>
+{
+    #[inline]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> [T; N] {
+        core::array::from_fn(|_| rng.gen())
+    }
+}
+
+impl<T> Distribution<Option<T>> for Standard
+where Standard: Distribution<T>
+{
+    #[inline]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Option<T> {
+        if rng.gen::<bool>() {
+            Some(rng.gen())
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Distribution<Wrapping<T>> for Standard
+where Standard: Distribution<T>
+{
+    #[inline]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Wrapping<T> {
+        Wrapping(rng.gen())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::Rand64;
+    use crate::{RngCore, RngExt, SeedableRng};
+
+    #[test]
+    fn standard_chars_are_valid_scalars() {
+        let mut rng = Rand64::seed_from_u64(21);
+        let mut above_gap = false;
+        for _ in 0..10_000 {
+            let c: char = rng.gen();
+            assert!(!(0xD800..=0xDFFF).contains(&(c as u32)));
+            above_gap |= c as u32 > 0xDFFF;
+        }
+        assert!(above_gap);
+    }
+
+    #[test]
+    fn standard_covers_compound_types() {
+        use core::num::{NonZeroU8, Wrapping};
+
+        let mut rng = Rand64::seed_from_u64(4);
+        let key: [u8; 32] = rng.gen();
+        assert_ne!(key, [0u8; 32]);
+
+        type Twelve = (u8, i16, u32, i64, u128, usize, bool, char, f32, f64, (), Option<i8>);
+        let _: Twelve = rng.gen();
+        let _: Wrapping<u64> = rng.gen();
+        let _: [[u16; 3]; 2] = rng.gen();
+
+        for _ in 0..1000 {
+            let x: f32 = rng.gen();
+            assert!((0.0..1.0).contains(&x));
+            let x: f64 = rng.gen();
+            assert!((0.0..1.0).contains(&x));
+            let _: NonZeroU8 = rng.gen();
+        }
+
+        let somes = (0..1000).filter(|_| rng.gen::<Option<()>>().is_some()).count();
+        assert!((400..600).contains(&somes));
+    }
+
+    #[test]
+    fn standard_is_reproducible() {
+        let mut a = Rand64::seed_from_u64(12);
+        let mut b = a;
+        assert_eq!(a.gen::<u32>(), b.next_u32());
+        assert_eq!(a.gen::<u64>(), b.next_u64());
+        assert_eq!(a.gen::<i8>(), b.next_u32() as i8);
+    }
+}
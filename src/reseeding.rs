@@ -0,0 +1,195 @@
+use crate::core::{Error, RngCore, SeedableRng};
+use crate::entropy::{self, EntropySource};
+
+/// A generator which is periodically reseeded from an entropy source.
+///
+/// Output is counted in bytes. Once `threshold` bytes have been generated,
+/// or after `request_reseed`, the generator is due for a reseed. Entropy on
+/// the IC can only be fetched with an async call, so the reseed itself
+/// happens in `reseed_if_needed`, which update methods should await before
+/// drawing; until then the current generator keeps producing output.
+#[derive(Clone, Debug)]
+pub struct ReseedingRng<G, S> {
+    rng: G,
+    source: S,
+    threshold: u64,
+    bytes_since_reseed: u64,
+    requested: bool,
+    reseeds: u64,
+}
+
+impl<G, S> ReseedingRng<G, S>
+where
+    G: RngCore + SeedableRng,
+    S: EntropySource,
+{
+    /// Wrap `rng`, reseeding it from `source` after every `threshold` bytes
+    /// of output. A threshold of 0 disables automatic reseeding.
+    pub fn new(rng: G, threshold: u64, source: S) -> Self {
+        Self {
+            rng,
+            source,
+            threshold,
+            bytes_since_reseed: 0,
+            requested: false,
+            reseeds: 0,
+        }
+    }
+
+    /// Create a generator seeded from `source`.
+    pub async fn from_source(threshold: u64, mut source: S) -> Result<Self, Error> {
+        let rng = entropy::from_entropy(&mut source).await?;
+        Ok(Self::new(rng, threshold, source))
+    }
+
+    /// Whether the next `reseed_if_needed` will reseed.
+    pub fn needs_reseed(&self) -> bool {
+        self.requested || (self.threshold > 0 && self.bytes_since_reseed >= self.threshold)
+    }
+
+    /// Reseed at the next `reseed_if_needed`, whatever the byte count.
+    pub fn request_reseed(&mut self) {
+        self.requested = true;
+    }
+
+    /// Reseed now if the threshold was reached or a reseed was requested.
+    ///
+    /// Returns whether a reseed happened. On error the current generator is
+    /// kept and stays due, so the next call tries again.
+    pub async fn reseed_if_needed(&mut self) -> Result<bool, Error> {
+        if !self.needs_reseed() {
+            return Ok(false);
+        }
+        self.reseed().await?;
+        Ok(true)
+    }
+
+    /// Reseed now from the entropy source.
+    pub async fn reseed(&mut self) -> Result<(), Error> {
+        let bytes = self.source.fetch_seed().await?;
+        self.rng = entropy::seed_from_bytes(bytes);
+        self.bytes_since_reseed = 0;
+        self.requested = false;
+        self.reseeds += 1;
+        Ok(())
+    }
+
+    /// Bytes generated since the last reseed.
+    pub fn bytes_since_reseed(&self) -> u64 {
+        self.bytes_since_reseed
+    }
+
+    /// The number of reseeds so far.
+    pub fn reseed_count(&self) -> u64 {
+        self.reseeds
+    }
+
+    pub fn inner(&self) -> &G {
+        &self.rng
+    }
+
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    /// Unwrap the generator and the entropy source.
+    pub fn into_parts(self) -> (G, S) {
+        (self.rng, self.source)
+    }
+
+    fn count(&mut self, bytes: usize) {
+        self.bytes_since_reseed = self.bytes_since_reseed.saturating_add(bytes as u64);
+    }
+}
+
+impl<G, S> RngCore for ReseedingRng<G, S>
+where
+    G: RngCore + SeedableRng,
+    S: EntropySource,
+{
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        self.count(4);
+        self.rng.next_u32()
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        self.count(8);
+        self.rng.next_u64()
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.count(dest.len());
+        self.rng.fill_bytes(dest)
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)?;
+        self.count(dest.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entropy::{block_on, MockEntropy};
+    use crate::utils::Rand64;
+
+    #[test]
+    fn reseeds_after_threshold() {
+        let mut rng = ReseedingRng::new(Rand64::seed_from_u64(0), 16, MockEntropy::new(1));
+        rng.next_u64();
+        assert!(!block_on(rng.reseed_if_needed()).unwrap());
+        rng.next_u32();
+        let mut buf = [0u8; 4];
+        rng.fill_bytes(&mut buf);
+        assert_eq!(rng.bytes_since_reseed(), 16);
+        assert!(rng.needs_reseed());
+
+        assert!(block_on(rng.reseed_if_needed()).unwrap());
+        assert_eq!(rng.bytes_since_reseed(), 0);
+        assert_eq!(rng.reseed_count(), 1);
+
+        // the new generator comes from the first mock seed
+        let expected: Rand64 = block_on(entropy::from_entropy(&mut MockEntropy::new(1))).unwrap();
+        assert_eq!(*rng.inner(), expected);
+    }
+
+    #[test]
+    fn explicit_requests() {
+        let mut rng = ReseedingRng::new(Rand64::seed_from_u64(0), 0, MockEntropy::new(2));
+        for _ in 0..1000 {
+            rng.next_u64();
+        }
+        // a zero threshold never reseeds on its own
+        assert!(!rng.needs_reseed());
+
+        rng.request_reseed();
+        assert!(block_on(rng.reseed_if_needed()).unwrap());
+        assert!(!rng.needs_reseed());
+        block_on(rng.reseed()).unwrap();
+        assert_eq!(rng.reseed_count(), 2);
+        assert_eq!(rng.source_mut().fetch_count(), 2);
+    }
+
+    #[test]
+    fn failed_reseed_keeps_generator() {
+        let seed = [4u8; 32];
+        let mut rng = block_on(ReseedingRng::<Rand64, _>::from_source(
+            8,
+            MockEntropy::from_seeds([seed]),
+        ))
+        .unwrap();
+        assert_eq!(*rng.inner(), Rand64::from_seed(seed));
+
+        let mut copy = *rng.inner();
+        assert_eq!(rng.next_u64(), copy.next_u64());
+        assert_eq!(block_on(rng.reseed_if_needed()), Err(Error::Unavailable));
+        assert!(rng.needs_reseed());
+        assert_eq!(rng.next_u64(), copy.next_u64());
+    }
+}
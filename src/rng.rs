@@ -0,0 +1,221 @@
+use crate::bernouilli::Bernoulli;
+use crate::core::RngCore;
+use crate::distribution::{DistIter, Distribution, Standard};
+use crate::uniform::{SampleRange, SampleUniform};
+
+/// Convenience methods available on every `RngCore`.
+///
+/// This is implemented for all generators, so it only needs to be imported:
+/// `use ic_rand::RngExt;`.
+pub trait RngExt: RngCore {
+    /// Return a random value of `T` from the `Standard` distribution.
+    #[inline]
+    fn gen<T>(&mut self) -> T
+    where
+        Standard: Distribution<T>,
+    {
+        Standard.sample(self)
+    }
+
+    /// Return a random value in `range`, which may be half-open (`a..b`)
+    /// or inclusive (`a..=b`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is empty. Use `Uniform::new` to handle that case
+    /// as an error instead.
+    #[inline]
+    fn gen_range<T, R>(&mut self, range: R) -> T
+    where
+        T: SampleUniform,
+        R: SampleRange<T>,
+    {
+        assert!(!range.is_empty(), "cannot sample empty range");
+        range.sample_single(self).unwrap()
+    }
+
+    /// Return `true` with probability `p`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` is not in `[0, 1]`.
+    #[inline]
+    fn gen_bool(&mut self, p: f64) -> bool {
+        match Bernoulli::new(p) {
+            Ok(d) => self.sample(d),
+            Err(_) => panic!("p={:?} is outside range [0.0, 1.0]", p),
+        }
+    }
+
+    /// Return `true` with probability `numerator / denominator`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `denominator == 0` or `numerator > denominator`.
+    #[inline]
+    fn gen_ratio(&mut self, numerator: u32, denominator: u32) -> bool {
+        match Bernoulli::from_ratio(numerator, denominator) {
+            Ok(d) => self.sample(d),
+            Err(_) => panic!(
+                "p={}/{} is outside range [0.0, 1.0]",
+                numerator, denominator
+            ),
+        }
+    }
+
+    /// Sample a value from `distr`.
+    #[inline]
+    fn sample<T, D: Distribution<T>>(&mut self, distr: D) -> T {
+        distr.sample(self)
+    }
+
+    /// Create an iterator sampling from `distr`, consuming the generator.
+    ///
+    /// Pass `&mut rng` to keep using the generator afterwards.
+    #[inline]
+    fn sample_iter<T, D>(self, distr: D) -> DistIter<D, Self, T>
+    where
+        D: Distribution<T>,
+        Self: Sized,
+    {
+        distr.sample_iter(self)
+    }
+
+    /// Fill `dest` with random data.
+    #[inline]
+    fn fill<T: Fill + ?Sized>(&mut self, dest: &mut T) {
+        dest.fill(self)
+    }
+}
+
+impl<R: RngCore + ?Sized> RngExt for R {}
+
+/// Types which may be filled with random data by `RngExt::fill`.
+pub trait Fill {
+    /// Fill `self` with random data from `rng`.
+    fn fill<R: RngCore + ?Sized>(&mut self, rng: &mut R);
+}
+
+impl Fill for [u8] {
+    #[inline]
+    fn fill<R: RngCore + ?Sized>(&mut self, rng: &mut R) {
+        rng.fill_bytes(self)
+    }
+}
+
+impl Fill for [bool] {
+    #[inline]
+    fn fill<R: RngCore + ?Sized>(&mut self, rng: &mut R) {
+        for elem in self.iter_mut() {
+            *elem = rng.gen();
+        }
+    }
+}
+
+macro_rules! fill_int_impl {
+    ($($ty:ty => $next:ident),* $(,)?) => {
+        $(
+            impl Fill for [$ty] {
+                #[inline]
+                fn fill<R: RngCore + ?Sized>(&mut self, rng: &mut R) {
+                    for elem in self.iter_mut() {
+                        *elem = rng.$next() as $ty;
+                    }
+                }
+            }
+        )*
+    };
+}
+
+fill_int_impl! {
+    u16 => next_u32,
+    u32 => next_u32,
+    u64 => next_u64,
+    usize => next_u64,
+    i8 => next_u32,
+    i16 => next_u32,
+    i32 => next_u32,
+    i64 => next_u64,
+    isize => next_u64,
+}
+
+impl Fill for [u128] {
+    #[inline]
+    fn fill<R: RngCore + ?Sized>(&mut self, rng: &mut R) {
+        for elem in self.iter_mut() {
+            let lo = u128::from(rng.next_u64());
+            let hi = u128::from(rng.next_u64());
+            *elem = (hi << 64) | lo;
+        }
+    }
+}
+
+impl Fill for [i128] {
+    #[inline]
+    fn fill<R: RngCore + ?Sized>(&mut self, rng: &mut R) {
+        for elem in self.iter_mut() {
+            let lo = u128::from(rng.next_u64());
+            let hi = u128::from(rng.next_u64());
+            *elem = ((hi << 64) | lo) as i128;
+        }
+    }
+}
+
+impl<T, const N: usize> Fill for [T; N]
+where
+    [T]: Fill,
+{
+    #[inline]
+    fn fill<R: RngCore + ?Sized>(&mut self, rng: &mut R) {
+        Fill::fill(&mut self[..], rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{Rand32, Rand64};
+    use crate::SeedableRng;
+
+    #[test]
+    fn gen_bool_and_ratio_edges() {
+        let mut rng = Rand32::seed_from_u64(11);
+        for _ in 0..100 {
+            assert!(rng.gen_bool(1.0));
+            assert!(!rng.gen_bool(0.0));
+            assert!(rng.gen_ratio(3, 3));
+            assert!(!rng.gen_ratio(0, 3));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn gen_bool_rejects_invalid_probability() {
+        Rand64::seed_from_u64(11).gen_bool(1.5);
+    }
+
+    #[test]
+    fn fill_matches_core_output() {
+        let mut a = Rand64::seed_from_u64(5);
+        let mut b = a;
+        let mut bytes = [0u8; 16];
+        a.fill(&mut bytes);
+        let mut expected = [0u8; 16];
+        b.fill_bytes(&mut expected);
+        assert_eq!(bytes, expected);
+
+        let mut words = [0u64; 4];
+        a.fill(&mut words);
+        for w in words {
+            assert_eq!(w, b.next_u64());
+        }
+    }
+
+    #[test]
+    fn sample_iter_by_reference_keeps_generator() {
+        let mut rng = Rand32::seed_from_u64(2);
+        let d = Bernoulli::new(1.0).unwrap();
+        assert!((&mut rng).sample_iter(d).take(10).all(|b| b));
+        let _: bool = rng.gen();
+    }
+}
@@ -1,6 +1,6 @@
 use crate::distribution::Distribution;
-use crate::uniform::{Uniform, UniformFloat};
-use crate::utils::Rng;
+use crate::uniform::Uniform;
+use crate::core::RngCore;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Slice<'a, T> {
@@ -22,7 +22,7 @@ impl<'a, T> Slice<'a, T> {
 }
 
 impl<'a, T> Distribution<&'a T> for Slice<'a, T>  {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> &'a T {
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> &'a T {
         let idx = self.range.sample(rng);
         debug_assert!(
             idx < self.slice.len(),
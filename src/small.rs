@@ -0,0 +1,391 @@
+//! Generators with one to four words of state.
+//!
+//! These are for cheap per-item streams and for expanding small seeds into
+//! the state of larger generators. None of them is cryptographically
+//! secure.
+
+use crate::core::{self as rng_core, Error, RngCore, SeedableRng};
+use crate::stable::{PersistentState, StateError};
+
+/// SplitMix64, the generator behind `SeedableRng::seed_from_u64`.
+///
+/// Every 64-bit state is valid and consecutive states give unrelated
+/// outputs, which makes it a good seed expander. `seed_from_u64` uses the
+/// value as the state directly, like `rand_xoshiro::SplitMix64`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), derive(serde::Deserialize))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(state: u64) -> Self {
+        Self { state }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+}
+
+impl RngCore for SplitMix64 {
+    /// The upper half of `next_u64`.
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rng_core::fill_bytes_via_next_u64(self, dest)
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for SplitMix64 {
+    /// The little-endian state.
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::new(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(state: u64) -> Self {
+        Self::new(state)
+    }
+}
+
+impl PersistentState for SplitMix64 {
+    const KIND: u8 = 17;
+    const STATE_LEN: usize = 8;
+
+    fn write_state(&self, out: &mut [u8]) {
+        out.copy_from_slice(&self.state.to_le_bytes());
+    }
+
+    fn read_state(bytes: &[u8]) -> Result<Self, StateError> {
+        let bytes: [u8; 8] = bytes.try_into().map_err(|_| StateError::Truncated)?;
+        Ok(Self::from_seed(bytes))
+    }
+}
+
+/// WyRand, from the final version 4.2 of wyhash.
+///
+/// A counter mixed with one 64x64 to 128-bit multiplication: the
+/// cheapest generator here, with the same outputs as the `wyrand` and
+/// `fastrand` crates for the same state.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), derive(serde::Deserialize))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
+pub struct WyRand {
+    state: u64,
+}
+
+impl WyRand {
+    pub fn new(state: u64) -> Self {
+        Self { state }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+}
+
+impl RngCore for WyRand {
+    /// The lower half of `next_u64`, as in `wyrand` and `fastrand`.
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x2d35_8dcc_aa6c_78a5);
+        let t = u128::from(self.state) * u128::from(self.state ^ 0x8bb8_4b93_962e_acc9);
+        (t as u64) ^ (t >> 64) as u64
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rng_core::fill_bytes_via_next_u64(self, dest)
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for WyRand {
+    /// The little-endian state.
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::new(u64::from_le_bytes(seed))
+    }
+}
+
+impl PersistentState for WyRand {
+    const KIND: u8 = 18;
+    const STATE_LEN: usize = 8;
+
+    fn write_state(&self, out: &mut [u8]) {
+        out.copy_from_slice(&self.state.to_le_bytes());
+    }
+
+    fn read_state(bytes: &[u8]) -> Result<Self, StateError> {
+        let bytes: [u8; 8] = bytes.try_into().map_err(|_| StateError::Truncated)?;
+        Ok(Self::from_seed(bytes))
+    }
+}
+
+/// Chris Doty-Humphrey's Small Fast Counting generators, SFC64 and SFC32.
+///
+/// `from_seed` reads the three state words and mixes them the way
+/// PractRand's three-word `seed` does, like the `rand_sfc` crate.
+macro_rules! sfc_impl {
+    (
+        $(#[$meta:meta])*
+        $name:ident: $word:ty, kind $kind:literal,
+        shifts ($rot:literal, $rshift:literal, $lshift:literal), mixing $rounds:literal $(,)?
+    ) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        #[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+        #[cfg_attr(any(feature = "serde1", feature = "candid"), derive(serde::Deserialize))]
+        #[cfg_attr(feature = "candid", derive(candid::CandidType))]
+        pub struct $name {
+            a: $word,
+            b: $word,
+            c: $word,
+            counter: $word,
+        }
+
+        impl $name {
+            const WORD_LEN: usize = core::mem::size_of::<$word>();
+
+            /// Start from the state words `a`, `b` and `c`, discarding the
+            /// first outputs to mix them.
+            pub fn new(a: $word, b: $word, c: $word) -> Self {
+                let mut rng = Self { a, b, c, counter: 1 };
+                for _ in 0..$rounds {
+                    rng.next_word();
+                }
+                rng
+            }
+
+            #[inline]
+            fn next_word(&mut self) -> $word {
+                let out = self.a.wrapping_add(self.b).wrapping_add(self.counter);
+                self.counter = self.counter.wrapping_add(1);
+                self.a = self.b ^ (self.b >> $rshift);
+                self.b = self.c.wrapping_add(self.c << $lshift);
+                self.c = self.c.rotate_left($rot).wrapping_add(out);
+                out
+            }
+
+            fn words(&self) -> [$word; 4] {
+                [self.a, self.b, self.c, self.counter]
+            }
+        }
+
+        impl SeedableRng for $name {
+            /// The words `a`, `b` and `c`, little-endian and passed to `new`.
+            type Seed = [u8; 3 * Self::WORD_LEN];
+
+            fn from_seed(seed: Self::Seed) -> Self {
+                let mut s = [0; 3];
+                for (w, chunk) in s.iter_mut().zip(seed.chunks_exact(Self::WORD_LEN)) {
+                    *w = <$word>::from_le_bytes(chunk.try_into().unwrap());
+                }
+                Self::new(s[0], s[1], s[2])
+            }
+        }
+
+        /// Stored as `a`, `b`, `c` and the counter, little-endian.
+        impl PersistentState for $name {
+            const KIND: u8 = $kind;
+            const STATE_LEN: usize = 4 * Self::WORD_LEN;
+
+            fn write_state(&self, out: &mut [u8]) {
+                for (w, chunk) in self.words().iter().zip(out.chunks_exact_mut(Self::WORD_LEN)) {
+                    chunk.copy_from_slice(&w.to_le_bytes());
+                }
+            }
+
+            fn read_state(bytes: &[u8]) -> Result<Self, StateError> {
+                if bytes.len() != Self::STATE_LEN {
+                    return Err(StateError::Truncated);
+                }
+                let mut s = [0; 4];
+                for (w, chunk) in s.iter_mut().zip(bytes.chunks_exact(Self::WORD_LEN)) {
+                    *w = <$word>::from_le_bytes(chunk.try_into().unwrap());
+                }
+                let [a, b, c, counter] = s;
+                Ok(Self { a, b, c, counter })
+            }
+        }
+    };
+}
+
+sfc_impl! {
+    /// SFC64: 256 bits of state including a 64-bit counter, so every seed
+    /// has a period of at least 2^64.
+    Sfc64: u64, kind 19,
+    shifts (24, 11, 3), mixing 18,
+}
+
+sfc_impl! {
+    /// SFC32: 128 bits of state including a 32-bit counter. Uses no
+    /// 64-bit arithmetic.
+    Sfc32: u32, kind 20,
+    shifts (21, 9, 3), mixing 15,
+}
+
+impl RngCore for Sfc64 {
+    /// The upper half of `next_u64`.
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        (self.next_word() >> 32) as u32
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        self.next_word()
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rng_core::fill_bytes_via_next_u64(self, dest)
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl RngCore for Sfc32 {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        self.next_word()
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        rng_core::next_u64_via_u32(self)
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rng_core::fill_bytes_via_next_u32(self, dest)
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::{decode_state, encode_state};
+
+    #[test]
+    fn splitmix64_reference() {
+        // from rand_xoshiro, produced with the reference implementation
+        let mut rng = SplitMix64::seed_from_u64(1234567);
+        let expected = [
+            6457827717110365317,
+            3203168211198807973,
+            9817491932198370423,
+            4593380528125082431,
+            16408922859458223821,
+        ];
+        for e in expected {
+            assert_eq!(rng.next_u64(), e);
+        }
+    }
+
+    #[test]
+    fn wyrand_reference() {
+        // the wyrand crate, which matches fastrand 2
+        let mut rng = WyRand::new(42);
+        let expected = [
+            14587678697106979209,
+            9105053682160394182,
+            14839644324764355487,
+            736379965966546952,
+            9998182218769833001,
+        ];
+        for e in expected {
+            assert_eq!(rng.next_u64(), e);
+        }
+    }
+
+    #[test]
+    fn sfc_reference() {
+        // PractRand's outputs, as used in the rand_sfc tests
+        let mut rng = Sfc64::new(1, 1, 1);
+        let expected = [
+            0xAD6FDC729FEEF3C1,
+            0x2A20433D733F77D5,
+            0x0310E21369647420,
+            0x331A176BC71DCABC,
+            0x53118F35C2494D94,
+            0xA3A99DE7E77E16BF,
+        ];
+        for e in expected {
+            assert_eq!(rng.next_u64(), e);
+        }
+
+        let mut seed = [0u8; 12];
+        seed[4] = 2;
+        seed[8] = 1;
+        let mut rng = Sfc32::from_seed(seed);
+        let expected = [
+            0x03B80BB8, 0xA87DBC7E, 0x1787178C, 0x4C7B7234, 0xC65DADE2, 0x2C692349,
+        ];
+        for e in expected {
+            assert_eq!(rng.next_u32(), e);
+        }
+    }
+
+    #[test]
+    fn states_round_trip() {
+        fn check<G>(mut rng: G)
+        where
+            G: PersistentState + RngCore + PartialEq + std::fmt::Debug,
+        {
+            rng.next_u64();
+            let mut restored: G = decode_state(&encode_state(&rng)).unwrap();
+            assert_eq!(restored, rng);
+            assert_eq!(restored.next_u64(), rng.next_u64());
+        }
+        check(SplitMix64::seed_from_u64(1));
+        check(WyRand::seed_from_u64(2));
+        check(Sfc64::seed_from_u64(3));
+        check(Sfc32::seed_from_u64(4));
+    }
+}
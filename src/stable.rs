@@ -0,0 +1,322 @@
+use std::fmt;
+
+use crate::utils::{Rand32, Rand64};
+
+/// Magic bytes at the start of every encoded generator state.
+const MAGIC: [u8; 4] = *b"ICRS";
+
+/// Current version of the encoding.
+pub const STATE_VERSION: u8 = 1;
+
+/// Size of the header in front of the state bytes: magic, version,
+/// generator kind and state length.
+pub const HEADER_LEN: usize = 8;
+
+/// A generator whose full state has a fixed-size binary encoding.
+///
+/// Restoring the encoded state continues the exact same stream, so a
+/// canister can save its generator in `pre_upgrade` and pick up where it
+/// left off in `post_upgrade`.
+pub trait PersistentState: Sized {
+    /// Identifies the generator type inside an encoded state.
+    const KIND: u8;
+
+    /// Length of the encoded state, excluding the header.
+    const STATE_LEN: usize;
+
+    /// Write the state into `out`, which is exactly `STATE_LEN` bytes.
+    fn write_state(&self, out: &mut [u8]);
+
+    /// Read a state written by `write_state`.
+    fn read_state(bytes: &[u8]) -> Result<Self, StateError>;
+}
+
+impl PersistentState for Rand32 {
+    const KIND: u8 = 1;
+    const STATE_LEN: usize = 16;
+
+    fn write_state(&self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_state(bytes: &[u8]) -> Result<Self, StateError> {
+        let bytes: [u8; 16] = bytes.try_into().map_err(|_| StateError::Truncated)?;
+        let rng = Rand32::from_le_bytes(bytes);
+        if rng.state().1 & 1 == 0 {
+            return Err(StateError::InvalidState);
+        }
+        Ok(rng)
+    }
+}
+
+impl PersistentState for Rand64 {
+    const KIND: u8 = 2;
+    const STATE_LEN: usize = 32;
+
+    fn write_state(&self, out: &mut [u8]) {
+        out.copy_from_slice(&self.to_le_bytes());
+    }
+
+    fn read_state(bytes: &[u8]) -> Result<Self, StateError> {
+        let bytes: [u8; 32] = bytes.try_into().map_err(|_| StateError::Truncated)?;
+        let rng = Rand64::from_le_bytes(bytes);
+        if rng.state().1 & 1 == 0 {
+            return Err(StateError::InvalidState);
+        }
+        Ok(rng)
+    }
+}
+
+/// Encode the state of `rng`, including the versioned header.
+pub fn encode_state<G: PersistentState>(rng: &G) -> Vec<u8> {
+    let mut out = vec![0u8; HEADER_LEN + G::STATE_LEN];
+    out[..4].copy_from_slice(&MAGIC);
+    out[4] = STATE_VERSION;
+    out[5] = G::KIND;
+    out[6..HEADER_LEN].copy_from_slice(&(G::STATE_LEN as u16).to_le_bytes());
+    rng.write_state(&mut out[HEADER_LEN..]);
+    out
+}
+
+/// Decode a state produced by `encode_state`.
+///
+/// Trailing bytes after the state are ignored, so a state can be read from
+/// the start of a larger region.
+pub fn decode_state<G: PersistentState>(bytes: &[u8]) -> Result<G, StateError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(StateError::Truncated);
+    }
+    if bytes[..4] != MAGIC {
+        return Err(StateError::BadHeader);
+    }
+    if bytes[4] != STATE_VERSION {
+        return Err(StateError::UnsupportedVersion);
+    }
+    if bytes[5] != G::KIND {
+        return Err(StateError::WrongGenerator);
+    }
+    let len = u16::from_le_bytes([bytes[6], bytes[7]]) as usize;
+    if len != G::STATE_LEN {
+        return Err(StateError::BadHeader);
+    }
+    let state = bytes
+        .get(HEADER_LEN..HEADER_LEN + len)
+        .ok_or(StateError::Truncated)?;
+    G::read_state(state)
+}
+
+/// A byte-addressed memory region that survives upgrades.
+pub trait StableMemory {
+    /// Current size of the region in bytes.
+    fn size(&self) -> u64;
+
+    /// Grow the region to at least `size` bytes.
+    fn grow_to(&mut self, size: u64) -> Result<(), StateError>;
+
+    /// Read `buf.len()` bytes starting at `offset`.
+    ///
+    /// The caller guarantees the range is within `size()`.
+    fn read(&self, offset: u64, buf: &mut [u8]);
+
+    /// Write `buf` starting at `offset`.
+    ///
+    /// The caller guarantees the range is within `size()`.
+    fn write(&mut self, offset: u64, buf: &[u8]);
+}
+
+/// Save the state of `rng` at `offset` in `memory`, growing it if needed.
+///
+/// Occupies `HEADER_LEN + G::STATE_LEN` bytes.
+pub fn save_state<G, M>(memory: &mut M, offset: u64, rng: &G) -> Result<(), StateError>
+where
+    G: PersistentState,
+    M: StableMemory + ?Sized,
+{
+    let bytes = encode_state(rng);
+    let end = offset
+        .checked_add(bytes.len() as u64)
+        .ok_or(StateError::OutOfMemory)?;
+    if memory.size() < end {
+        memory.grow_to(end)?;
+    }
+    memory.write(offset, &bytes);
+    Ok(())
+}
+
+/// Restore a generator saved with `save_state` at `offset` in `memory`.
+pub fn restore_state<G, M>(memory: &M, offset: u64) -> Result<G, StateError>
+where
+    G: PersistentState,
+    M: StableMemory + ?Sized,
+{
+    let mut bytes = vec![0u8; HEADER_LEN + G::STATE_LEN];
+    let end = offset
+        .checked_add(bytes.len() as u64)
+        .ok_or(StateError::Truncated)?;
+    if memory.size() < end {
+        return Err(StateError::Truncated);
+    }
+    memory.read(offset, &mut bytes);
+    decode_state(&bytes)
+}
+
+/// An in-memory `StableMemory`, for tests and off-chain tools.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VecMemory {
+    bytes: Vec<u8>,
+}
+
+impl VecMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl StableMemory for VecMemory {
+    fn size(&self) -> u64 {
+        self.bytes.len() as u64
+    }
+
+    fn grow_to(&mut self, size: u64) -> Result<(), StateError> {
+        let size = usize::try_from(size).map_err(|_| StateError::OutOfMemory)?;
+        if size > self.bytes.len() {
+            self.bytes.resize(size, 0);
+        }
+        Ok(())
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) {
+        let offset = offset as usize;
+        buf.copy_from_slice(&self.bytes[offset..offset + buf.len()]);
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) {
+        let offset = offset as usize;
+        self.bytes[offset..offset + buf.len()].copy_from_slice(buf);
+    }
+}
+
+/// The canister's stable memory, through the IC system API.
+#[cfg(feature = "ic")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IcStableMemory;
+
+#[cfg(feature = "ic")]
+impl StableMemory for IcStableMemory {
+    fn size(&self) -> u64 {
+        ic_cdk::stable::stable_size() * ic_cdk::stable::WASM_PAGE_SIZE_IN_BYTES
+    }
+
+    fn grow_to(&mut self, size: u64) -> Result<(), StateError> {
+        let page = ic_cdk::stable::WASM_PAGE_SIZE_IN_BYTES;
+        let pages = size.div_ceil(page);
+        let current = ic_cdk::stable::stable_size();
+        if pages > current {
+            ic_cdk::stable::stable_grow(pages - current).map_err(|_| StateError::OutOfMemory)?;
+        }
+        Ok(())
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) {
+        ic_cdk::stable::stable_read(offset, buf)
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) {
+        ic_cdk::stable::stable_write(offset, buf)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The bytes do not start with a generator state header.
+    BadHeader,
+    /// The state was written by an unknown version of the encoding.
+    UnsupportedVersion,
+    /// The state belongs to a different generator type.
+    WrongGenerator,
+    /// The state is shorter than its header says.
+    Truncated,
+    /// The state decodes to a value the generator cannot be in.
+    InvalidState,
+    /// The memory could not be grown to hold the state.
+    OutOfMemory,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StateError::BadHeader => "not a generator state",
+            StateError::UnsupportedVersion => "unsupported generator state version",
+            StateError::WrongGenerator => "generator state is for a different generator",
+            StateError::Truncated => "generator state is truncated",
+            StateError::InvalidState => "generator state is invalid",
+            StateError::OutOfMemory => "not enough memory to save generator state",
+        })
+    }
+}
+
+impl std::error::Error for StateError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RngCore, SeedableRng};
+
+    #[test]
+    fn upgrade_continues_the_stream() {
+        let mut memory = VecMemory::new();
+        let mut rng = Rand64::seed_from_u64(1);
+        let _ = rng.next_u64();
+        save_state(&mut memory, 100, &rng).unwrap();
+        assert_eq!(memory.size(), 100 + (HEADER_LEN + 32) as u64);
+
+        let mut restored: Rand64 = restore_state(&memory, 100).unwrap();
+        for _ in 0..10 {
+            assert_eq!(restored.next_u64(), rng.next_u64());
+        }
+
+        let mut rng = Rand32::seed_from_u64(1);
+        save_state(&mut memory, 0, &rng).unwrap();
+        let mut restored: Rand32 = restore_state(&memory, 0).unwrap();
+        assert_eq!(restored.next_u32(), rng.next_u32());
+    }
+
+    #[test]
+    fn rand32_state_keeps_the_increment() {
+        let rng = Rand32::new_inc(5, 11);
+        assert_eq!(rng.state().1, (11 << 1) | 1);
+        assert_eq!(Rand32::from_state(rng.state()), rng);
+    }
+
+    #[test]
+    fn corrupt_states_are_rejected() {
+        let rng = Rand64::seed_from_u64(2);
+        let good = encode_state(&rng);
+        assert_eq!(decode_state::<Rand64>(&good), Ok(rng));
+        assert_eq!(decode_state::<Rand32>(&good), Err(StateError::WrongGenerator));
+        assert_eq!(decode_state::<Rand64>(&good[..20]), Err(StateError::Truncated));
+
+        let mut bad = good.clone();
+        bad[0] = b'X';
+        assert_eq!(decode_state::<Rand64>(&bad), Err(StateError::BadHeader));
+
+        let mut bad = good.clone();
+        bad[4] = STATE_VERSION + 1;
+        assert_eq!(decode_state::<Rand64>(&bad), Err(StateError::UnsupportedVersion));
+
+        let mut bad = good;
+        bad[HEADER_LEN + 16] &= !1;
+        assert_eq!(decode_state::<Rand64>(&bad), Err(StateError::InvalidState));
+
+        let empty = VecMemory::new();
+        assert_eq!(restore_state::<Rand64, _>(&empty, 0), Err(StateError::Truncated));
+    }
+}
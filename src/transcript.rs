@@ -0,0 +1,477 @@
+//! Transcripts of draws, for proving after the fact that they were fair.
+//!
+//! A `Recorder` wraps a generator and logs its initial state, the seed it
+//! came from if known, and the parameters and outputs of every draw. Anyone
+//! holding the transcript can run `replay` to check that the recorded
+//! outputs are exactly what the generator produces.
+
+use std::fmt;
+
+use crate::chacha::{ChaCha12Rng, ChaCha20Rng, ChaCha8Rng};
+use crate::core::{RngCore, SeedableRng};
+use crate::distribution::Distribution;
+use crate::pcg::{Pcg32Oneseq, Pcg32XshRs, Pcg64Dxsm, Pcg64Mcg};
+use crate::small::{Sfc32, Sfc64, SplitMix64, WyRand};
+use crate::stable::{self, PersistentState, StateError};
+use crate::uniform::Uniform;
+use crate::utils::{Rand32, Rand64};
+use crate::weight_index::WeightIndex;
+use crate::xoshiro::{
+    Xoroshiro128PlusPlus, Xoroshiro128StarStar, Xoshiro128PlusPlus, Xoshiro128StarStar,
+    Xoshiro256Plus, Xoshiro256PlusPlus, Xoshiro256StarStar,
+};
+
+/// Magic bytes at the start of an encoded transcript.
+const MAGIC: [u8; 4] = *b"ICRT";
+
+/// Current version of the transcript encoding.
+pub const TRANSCRIPT_VERSION: u8 = 1;
+
+/// The distribution of one draw.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), derive(serde::Deserialize))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
+pub enum DrawParams {
+    /// Raw `next_u64` output.
+    Raw,
+    /// A `Uniform<u64>` over `low..=high`.
+    Uniform { low: u64, high: u64 },
+    /// A `WeightIndex<u64>`, as returned by its `cumulative_weights` and
+    /// `total_weight`.
+    WeightIndex {
+        cumulative_weights: Vec<u64>,
+        total_weight: u64,
+    },
+}
+
+impl DrawParams {
+    /// The parameters of `dist`.
+    pub fn weight_index(dist: &WeightIndex<u64>) -> Self {
+        DrawParams::WeightIndex {
+            cumulative_weights: dist.cumulative_weights().to_vec(),
+            total_weight: *dist.total_weight(),
+        }
+    }
+
+    /// Draw `count` values from the distribution.
+    pub fn sample<R: RngCore + ?Sized>(
+        &self,
+        rng: &mut R,
+        count: usize,
+    ) -> Result<Vec<u64>, TranscriptError> {
+        match self {
+            DrawParams::Raw => Ok((0..count).map(|_| rng.next_u64()).collect()),
+            DrawParams::Uniform { low, high } => {
+                let dist = Uniform::new_inclusive(*low, *high)
+                    .map_err(|_| TranscriptError::InvalidParams)?;
+                Ok((0..count).map(|_| dist.sample(rng)).collect())
+            }
+            DrawParams::WeightIndex {
+                cumulative_weights,
+                total_weight,
+            } => {
+                let dist = rebuild_weight_index(cumulative_weights, *total_weight)?;
+                Ok((0..count).map(|_| dist.sample(rng) as u64).collect())
+            }
+        }
+    }
+}
+
+/// Rebuild a `WeightIndex` from its running totals.
+fn rebuild_weight_index(
+    cumulative: &[u64],
+    total: u64,
+) -> Result<WeightIndex<u64>, TranscriptError> {
+    let mut weights = Vec::with_capacity(cumulative.len() + 1);
+    let mut previous = 0u64;
+    for &running in cumulative.iter().chain([&total]) {
+        let w = running
+            .checked_sub(previous)
+            .ok_or(TranscriptError::InvalidParams)?;
+        weights.push(w);
+        previous = running;
+    }
+    WeightIndex::new(weights).map_err(|_| TranscriptError::InvalidParams)
+}
+
+/// One recorded draw.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), derive(serde::Deserialize))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
+pub struct Draw {
+    pub params: DrawParams,
+    pub outputs: Vec<u64>,
+}
+
+/// The record of a sequence of draws from one generator.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), derive(serde::Deserialize))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
+pub struct Transcript {
+    /// The seed the generator was created from, or empty if unknown.
+    pub seed: Vec<u8>,
+    /// The generator state before the first draw, as written by
+    /// `stable::encode_state`. This also records the generator type.
+    pub state: Vec<u8>,
+    pub draws: Vec<Draw>,
+}
+
+impl Transcript {
+    /// Encode the transcript compactly.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&MAGIC);
+        out.push(TRANSCRIPT_VERSION);
+        put_bytes(&mut out, &self.seed);
+        put_bytes(&mut out, &self.state);
+        put_len(&mut out, self.draws.len());
+        for draw in &self.draws {
+            match &draw.params {
+                DrawParams::Raw => out.push(0),
+                DrawParams::Uniform { low, high } => {
+                    out.push(1);
+                    out.extend_from_slice(&low.to_le_bytes());
+                    out.extend_from_slice(&high.to_le_bytes());
+                }
+                DrawParams::WeightIndex {
+                    cumulative_weights,
+                    total_weight,
+                } => {
+                    out.push(2);
+                    put_words(&mut out, cumulative_weights);
+                    out.extend_from_slice(&total_weight.to_le_bytes());
+                }
+            }
+            put_words(&mut out, &draw.outputs);
+        }
+        out
+    }
+
+    /// Decode a transcript written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TranscriptError> {
+        let mut r = Reader(bytes);
+        if r.take(4)? != MAGIC {
+            return Err(TranscriptError::Malformed);
+        }
+        if r.take(1)?[0] != TRANSCRIPT_VERSION {
+            return Err(TranscriptError::Malformed);
+        }
+        let seed = r.bytes()?.to_vec();
+        let state = r.bytes()?.to_vec();
+        let count = r.len()?;
+        let mut draws = Vec::new();
+        for _ in 0..count {
+            let params = match r.take(1)?[0] {
+                0 => DrawParams::Raw,
+                1 => DrawParams::Uniform {
+                    low: r.word()?,
+                    high: r.word()?,
+                },
+                2 => DrawParams::WeightIndex {
+                    cumulative_weights: r.words()?,
+                    total_weight: r.word()?,
+                },
+                _ => return Err(TranscriptError::Malformed),
+            };
+            let outputs = r.words()?;
+            draws.push(Draw { params, outputs });
+        }
+        if !r.0.is_empty() {
+            return Err(TranscriptError::Malformed);
+        }
+        Ok(Transcript { seed, state, draws })
+    }
+}
+
+fn put_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_len(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+fn put_words(out: &mut Vec<u8>, words: &[u64]) {
+    put_len(out, words.len());
+    for w in words {
+        out.extend_from_slice(&w.to_le_bytes());
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], TranscriptError> {
+        if self.0.len() < n {
+            return Err(TranscriptError::Malformed);
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn len(&mut self) -> Result<usize, TranscriptError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes(b.try_into().unwrap()) as usize)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], TranscriptError> {
+        let n = self.len()?;
+        self.take(n)
+    }
+
+    fn word(&mut self) -> Result<u64, TranscriptError> {
+        let b = self.take(8)?;
+        Ok(u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn words(&mut self) -> Result<Vec<u64>, TranscriptError> {
+        let n = self.len()?;
+        // check the length up front so a corrupt count cannot allocate
+        if self.0.len() / 8 < n {
+            return Err(TranscriptError::Malformed);
+        }
+        (0..n).map(|_| self.word()).collect()
+    }
+}
+
+/// A generator which records every draw into a `Transcript`.
+#[derive(Clone, Debug)]
+pub struct Recorder<G> {
+    rng: G,
+    transcript: Transcript,
+}
+
+impl<G: PersistentState + RngCore> Recorder<G> {
+    /// Record draws from `rng`, starting from its current state.
+    pub fn new(rng: G) -> Self {
+        let transcript = Transcript {
+            seed: Vec::new(),
+            state: stable::encode_state(&rng),
+            draws: Vec::new(),
+        };
+        Self { rng, transcript }
+    }
+
+    /// Record draws from a generator created from `seed`, so the
+    /// transcript also shows where the state came from.
+    pub fn from_seed(mut seed: G::Seed) -> Self
+    where
+        G: SeedableRng,
+    {
+        let bytes = seed.as_mut().to_vec();
+        let mut recorder = Self::new(G::from_seed(seed));
+        recorder.transcript.seed = bytes;
+        recorder
+    }
+
+    /// Draw and record `count` values.
+    pub fn draw(&mut self, params: DrawParams, count: usize) -> Result<Vec<u64>, TranscriptError> {
+        let outputs = params.sample(&mut self.rng, count)?;
+        self.transcript.draws.push(Draw {
+            params,
+            outputs: outputs.clone(),
+        });
+        Ok(outputs)
+    }
+
+    /// Draw and record `count` indices from `dist`.
+    pub fn draw_weighted(&mut self, dist: &WeightIndex<u64>, count: usize) -> Vec<usize> {
+        let outputs = self
+            .draw(DrawParams::weight_index(dist), count)
+            .expect("a valid WeightIndex rebuilds from its weights");
+        outputs.into_iter().map(|i| i as usize).collect()
+    }
+
+    pub fn transcript(&self) -> &Transcript {
+        &self.transcript
+    }
+
+    /// Stop recording, returning the generator and the transcript.
+    pub fn finish(self) -> (G, Transcript) {
+        (self.rng, self.transcript)
+    }
+}
+
+/// Re-run the draws in `transcript` and check that they match.
+///
+/// If the transcript has a seed, the recorded state must also be the one
+/// that seed produces.
+pub fn replay(transcript: &Transcript) -> Result<(), TranscriptError> {
+    match transcript.state.get(5).copied() {
+        Some(Rand32::KIND) => replay_with::<Rand32>(transcript),
+        Some(Rand64::KIND) => replay_with::<Rand64>(transcript),
+        Some(ChaCha8Rng::KIND) => replay_with::<ChaCha8Rng>(transcript),
+        Some(ChaCha12Rng::KIND) => replay_with::<ChaCha12Rng>(transcript),
+        Some(ChaCha20Rng::KIND) => replay_with::<ChaCha20Rng>(transcript),
+        Some(Pcg64Dxsm::KIND) => replay_with::<Pcg64Dxsm>(transcript),
+        Some(Pcg64Mcg::KIND) => replay_with::<Pcg64Mcg>(transcript),
+        Some(Pcg32Oneseq::KIND) => replay_with::<Pcg32Oneseq>(transcript),
+        Some(Pcg32XshRs::KIND) => replay_with::<Pcg32XshRs>(transcript),
+        Some(Xoshiro128PlusPlus::KIND) => replay_with::<Xoshiro128PlusPlus>(transcript),
+        Some(Xoshiro128StarStar::KIND) => replay_with::<Xoshiro128StarStar>(transcript),
+        Some(Xoshiro256PlusPlus::KIND) => replay_with::<Xoshiro256PlusPlus>(transcript),
+        Some(Xoshiro256StarStar::KIND) => replay_with::<Xoshiro256StarStar>(transcript),
+        Some(Xoshiro256Plus::KIND) => replay_with::<Xoshiro256Plus>(transcript),
+        Some(Xoroshiro128PlusPlus::KIND) => replay_with::<Xoroshiro128PlusPlus>(transcript),
+        Some(Xoroshiro128StarStar::KIND) => replay_with::<Xoroshiro128StarStar>(transcript),
+        Some(SplitMix64::KIND) => replay_with::<SplitMix64>(transcript),
+        Some(WyRand::KIND) => replay_with::<WyRand>(transcript),
+        Some(Sfc64::KIND) => replay_with::<Sfc64>(transcript),
+        Some(Sfc32::KIND) => replay_with::<Sfc32>(transcript),
+        Some(_) => Err(TranscriptError::State(StateError::WrongGenerator)),
+        None => Err(TranscriptError::State(StateError::Truncated)),
+    }
+}
+
+/// `replay` for a transcript known to use generator `G`.
+pub fn replay_with<G>(transcript: &Transcript) -> Result<(), TranscriptError>
+where
+    G: PersistentState + SeedableRng + RngCore + PartialEq,
+{
+    let mut rng: G = stable::decode_state(&transcript.state).map_err(TranscriptError::State)?;
+    if !transcript.seed.is_empty() {
+        let mut seed = G::Seed::default();
+        if seed.as_mut().len() != transcript.seed.len() {
+            return Err(TranscriptError::SeedMismatch);
+        }
+        seed.as_mut().copy_from_slice(&transcript.seed);
+        if G::from_seed(seed) != rng {
+            return Err(TranscriptError::SeedMismatch);
+        }
+    }
+    for (i, draw) in transcript.draws.iter().enumerate() {
+        let outputs = draw.params.sample(&mut rng, draw.outputs.len())?;
+        if outputs != draw.outputs {
+            return Err(TranscriptError::OutputMismatch { draw: i });
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TranscriptError {
+    /// The encoded transcript is corrupt.
+    Malformed,
+    /// The recorded generator state cannot be decoded.
+    State(StateError),
+    /// The recorded parameters do not describe a valid distribution.
+    InvalidParams,
+    /// The recorded state is not the one the recorded seed produces.
+    SeedMismatch,
+    /// The outputs of the draw at this index do not match the replay.
+    OutputMismatch { draw: usize },
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscriptError::Malformed => f.write_str("malformed transcript"),
+            TranscriptError::State(err) => write!(f, "bad transcript state: {}", err),
+            TranscriptError::InvalidParams => f.write_str("invalid distribution parameters"),
+            TranscriptError::SeedMismatch => f.write_str("state does not match seed"),
+            TranscriptError::OutputMismatch { draw } => {
+                write!(f, "outputs of draw {} do not match replay", draw)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TranscriptError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raffle() -> Transcript {
+        let mut recorder = Recorder::<Rand64>::from_seed([3; 32]);
+        let tickets = WeightIndex::new([5u64, 1, 0, 10]).unwrap();
+        let winners = recorder.draw_weighted(&tickets, 3);
+        assert!(winners.iter().all(|&i| i != 2));
+        recorder
+            .draw(DrawParams::Uniform { low: 1, high: 6 }, 4)
+            .unwrap();
+        recorder.draw(DrawParams::Raw, 1).unwrap();
+        recorder.finish().1
+    }
+
+    #[test]
+    fn recorded_draws_replay() {
+        let transcript = raffle();
+        assert_eq!(transcript.draws.len(), 3);
+        assert_eq!(
+            transcript.draws[0].params,
+            DrawParams::WeightIndex {
+                cumulative_weights: vec![5, 6, 6],
+                total_weight: 16
+            }
+        );
+        assert_eq!(replay(&transcript), Ok(()));
+
+        let mut rng = Rand32::seed_from_u64(1);
+        rng.next_u32();
+        let mut recorder = Recorder::new(rng);
+        recorder.draw(DrawParams::Raw, 5).unwrap();
+        assert!(recorder.transcript().seed.is_empty());
+        assert_eq!(replay(recorder.transcript()), Ok(()));
+
+        let mut recorder = Recorder::<ChaCha20Rng>::from_seed([8; 32]);
+        recorder.draw(DrawParams::Uniform { low: 0, high: 99 }, 3).unwrap();
+        assert_eq!(replay(recorder.transcript()), Ok(()));
+
+        let mut recorder = Recorder::<Pcg64Dxsm>::from_seed([9; 32]);
+        recorder.draw(DrawParams::Raw, 4).unwrap();
+        assert_eq!(replay(recorder.transcript()), Ok(()));
+
+        let mut recorder = Recorder::<Xoshiro128PlusPlus>::from_seed([10; 16]);
+        recorder.draw(DrawParams::Raw, 4).unwrap();
+        assert_eq!(replay(recorder.transcript()), Ok(()));
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let transcript = raffle();
+
+        let mut forged = transcript.clone();
+        forged.draws[1].outputs[2] = 7;
+        assert_eq!(
+            replay(&forged),
+            Err(TranscriptError::OutputMismatch { draw: 1 })
+        );
+
+        let mut forged = transcript.clone();
+        forged.seed[0] ^= 1;
+        assert_eq!(replay(&forged), Err(TranscriptError::SeedMismatch));
+
+        let mut forged = transcript.clone();
+        forged.draws[0].params = DrawParams::WeightIndex {
+            cumulative_weights: vec![5, 4],
+            total_weight: 10,
+        };
+        assert_eq!(replay(&forged), Err(TranscriptError::InvalidParams));
+
+        let mut forged = transcript;
+        forged.state[5] = 200;
+        assert_eq!(
+            replay(&forged),
+            Err(TranscriptError::State(StateError::WrongGenerator))
+        );
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let transcript = raffle();
+        let bytes = transcript.to_bytes();
+        assert_eq!(Transcript::from_bytes(&bytes), Ok(transcript));
+        for len in 0..bytes.len() {
+            assert_eq!(
+                Transcript::from_bytes(&bytes[..len]),
+                Err(TranscriptError::Malformed)
+            );
+        }
+    }
+}
@@ -1,13 +1,19 @@
 use std::fmt;
 use std::ops::{RangeInclusive, Range};
 
-use crate::distribution::Distribution;
-use crate::Rng;
+use crate::distribution::{Distribution, Standard};
+use crate::core::RngCore;
+
+#[cfg(feature="serde1")]
+use serde::Serialize;
+#[cfg(any(feature="serde1", feature="candid"))]
+use serde::Deserialize;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Error {
     EmptyRange,
     NonFinite,
+    InvalidBounds,
 }
 
 impl fmt::Display for Error {
@@ -15,14 +21,16 @@ impl fmt::Display for Error {
         f.write_str(match self {
             Error::EmptyRange => "low > high (for equal if exclusive) in uniform distribution",
             Error::NonFinite => "non-finite range in uniform distribution",
+            Error::InvalidBounds => "bounds are not valid values of the sampled type",
         })
     }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
-#[cfg_attr(feature = "serde1", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde1", derive(Serialize))]
 #[cfg_attr(feature = "serde1", serde(bound(serialize = "X::Sampler: Serialize")))]
-#[cfg_attr(feature = "serde1", serde(bound(deserialize = "X::Sampler: Deserialize<'de>")))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), derive(Deserialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), serde(bound(deserialize = "X::Sampler: Deserialize<'de>")))]
 pub struct  Uniform<X: SampleUniform>(X::Sampler);
 
 impl<X: SampleUniform> Uniform<X> {
@@ -49,8 +57,24 @@ impl<X: SampleUniform> Uniform<X> {
     }
 }
 
+// candid's derive cannot bound `X::Sampler`, and a `Uniform` is encoded
+// exactly like its sampler anyway
+#[cfg(feature = "candid")]
+impl<X: SampleUniform> candid::CandidType for Uniform<X>
+where
+    X::Sampler: candid::CandidType,
+{
+    fn _ty() -> candid::types::Type {
+        X::Sampler::ty()
+    }
+
+    fn idl_serialize<S: candid::types::Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        self.0.idl_serialize(serializer)
+    }
+}
+
 impl<X: SampleUniform> Distribution<X> for Uniform<X> {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> X {
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> X {
         self.0.sample(rng)
     }
 }
@@ -73,9 +97,9 @@ pub trait  UniformSampler: Sized {
         B1: SampleBorrow<Self::X> + Sized,
         B2: SampleBorrow<Self::X> + Sized;
 
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Self::X;
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Self::X;
 
-    fn sample_single<R: Rng + ?Sized, B1, B2>(low: B1, high: B2, rng: &mut R) -> Result<Self::X, Error>
+    fn sample_single<R: RngCore + ?Sized, B1, B2>(low: B1, high: B2, rng: &mut R) -> Result<Self::X, Error>
     where
         B1: SampleBorrow<Self::X> + Sized,
         B2: SampleBorrow<Self::X> + Sized,
@@ -84,7 +108,7 @@ pub trait  UniformSampler: Sized {
         Ok(uniform.sample(rng))
     }
 
-    fn sample_single_inclusive<R: Rng + ?Sized, B1, B2>(low: B1, high: B2, rng: &mut R) 
+    fn sample_single_inclusive<R: RngCore + ?Sized, B1, B2>(low: B1, high: B2, rng: &mut R) 
     -> Result<Self::X, Error> 
     where 
         B1: SampleBorrow<Self::X> + Sized,
@@ -123,7 +147,7 @@ where Borrowed: SampleUniform
     }
 }
 
-impl<'a, Borrowed> SampleBorrow<Borrowed> for &'a Borrowed
+impl<Borrowed> SampleBorrow<Borrowed> for &Borrowed
 where Borrowed: SampleUniform
 {
     fn borrow(&self) -> &Borrowed {
@@ -132,13 +156,13 @@ where Borrowed: SampleUniform
 }
 
 pub trait SampleRange<T> {
-    fn sample_single<R: Rng + ?Sized> (self, rng: &mut R) -> Result<T, Error>;
+    fn sample_single<R: RngCore + ?Sized> (self, rng: &mut R) -> Result<T, Error>;
 
     fn is_empty(&self) -> bool;
 }
 
 impl<T: SampleUniform + PartialOrd> SampleRange<T> for Range<T> {
-    fn sample_single<R: Rng + ?Sized> (self, rng: &mut R) -> Result<T, Error> {
+    fn sample_single<R: RngCore + ?Sized> (self, rng: &mut R) -> Result<T, Error> {
         T::Sampler::sample_single(self.start, self.end, rng)
     }
 
@@ -148,7 +172,7 @@ impl<T: SampleUniform + PartialOrd> SampleRange<T> for Range<T> {
 }
 
 impl<T: SampleUniform + PartialOrd> SampleRange<T> for RangeInclusive<T> {
-    fn sample_single<R: Rng + ?Sized> (self, rng: &mut R) -> Result<T, Error> {
+    fn sample_single<R: RngCore + ?Sized> (self, rng: &mut R) -> Result<T, Error> {
         T::Sampler::sample_single_inclusive(self.start(), self.end(), rng)
     }
 
@@ -158,91 +182,294 @@ impl<T: SampleUniform + PartialOrd> SampleRange<T> for RangeInclusive<T> {
 }
 
 #[derive(Clone, Copy, Debug ,PartialEq)]
-#[cfg_attr(feature="serde1", derive(Serialize, Deserialize))]
+#[cfg_attr(feature="serde1", derive(Serialize))]
+#[cfg_attr(any(feature="serde1", feature="candid"), derive(Deserialize))]
+#[cfg_attr(any(feature="serde1", feature="candid"), serde(try_from = "UniformIntRecord<X>"))]
+#[cfg_attr(any(feature="serde1", feature="candid"), serde(bound(deserialize = "X: Deserialize<'de>, UniformInt<X>: TryFrom<UniformIntRecord<X>, Error = Error>")))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
 pub struct UniformInt<X> {
     low: X,
     range: X,
     z: X,
 }
 
-// macro_rules! uniform_int_impl {
-//     ($ty: ty, $unsigned: ident, $u_large: ident) => {
-//         impl SampleUniform for $ty {
-//             type Sampler = UniformInt<$ty>;
-//         }
-
-//         implUniformeSampler for UniformInt<$ty> {
-//             type X = $ty;
-
-//             #[inline]
-//             fn new<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
-//             where
-//                 B1: SampleBorrow<Self::X> + Sized,
-//                 B2: SampleBorrow<Self::X> + Sized,
-//             {
-//                 let low = *low_b.borrow();
-//                 let high = *high_b.borrow();
-//                 if !(low < high) {
-//                     return Err(Error::EmptyRange);
-//                 }
-//                UniformeSampler::new_inclusive(low, high - 1)
-//             }
-
-//             #[inline]
-//             fn new_inclusive<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
-//             where
-//                 B1: SampleBorrow<Self::X> + Sized,
-//                 B2: SampleBorrow<Self::X> + Sized,
-//             {
-//                 let low = *low_b.borrow();
-//                 let high = *high_b.borrow();
-
-//                 if !(low <= high) {
-//                     return Err(Error::EmptyRange);
-//                 }
-//                 let unsigned_max = ::core::$u_large::MAX;
-
-//                 let range = high.wrapping_sub(low).wrapping_add(1) as $unsigned;
-//                 let ints_to_reject = if range > 0 {
-//                     let range = $u_large::from(range);
-//                     (unsigned_max - range + 1) % range
-//                 } else {
-//                     0
-//                 };
-
-//                 Ok(UniformInt {
-//                     low ,
-//                     range: range as $ty,
-//                     z: ints_to_reject as $unsigned as $ty,
-//                 })
-//             }
-
-//             #[inline]
-//             fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Self::X {
-//                 let range = self.range as $unsigned as $u_large;
-//                 if range > 0 {
-//                     let unsigned_max = ::core::$u_large::MAX;
-//                     let zone = unsigned_max - (self.z as $unsigned as $u_large);
-//                     loop {
-//                         let v: $u_large = rng.
-//                     }
-//                 }
-//             }
-//         }
-//     };
-
-// }
-
-// impl SampleUniform for char {
-//     type Sampler = UniformChar;
-// }
+// the encoded form of `UniformInt`; decoding rebuilds the sampler from
+// `low` and `range`, since `sample` never returns if `z` leaves no
+// accepted zone
+#[cfg(any(feature="serde1", feature="candid"))]
+#[derive(Deserialize)]
+struct UniformIntRecord<X> {
+    low: X,
+    range: X,
+    // recomputed rather than trusted
+    #[allow(dead_code)]
+    z: X,
+}
+
+macro_rules! uniform_int_impl {
+    ($ty: ty, $unsigned: ident, $u_large: ident) => {
+        impl SampleUniform for $ty {
+            type Sampler = UniformInt<$ty>;
+        }
+
+        #[cfg(any(feature="serde1", feature="candid"))]
+        impl TryFrom<UniformIntRecord<$ty>> for UniformInt<$ty> {
+            type Error = Error;
+
+            fn try_from(record: UniformIntRecord<$ty>) -> Result<Self, Error> {
+                let UniformIntRecord { low, range, .. } = record;
+                // a zero range is the full range, which only starts at the
+                // minimum; otherwise `high` must not wrap past the maximum
+                let high = low.wrapping_add((range as $unsigned).wrapping_sub(1) as $ty);
+                if high < low {
+                    return Err(Error::InvalidBounds);
+                }
+                UniformSampler::new_inclusive(low, high)
+            }
+        }
+
+        impl UniformSampler for UniformInt<$ty> {
+            type X = $ty;
+
+            #[inline]
+            fn new<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
+            where
+                B1: SampleBorrow<Self::X> + Sized,
+                B2: SampleBorrow<Self::X> + Sized,
+            {
+                let low = *low_b.borrow();
+                let high = *high_b.borrow();
+                if !(low < high) {
+                    return Err(Error::EmptyRange);
+                }
+                UniformSampler::new_inclusive(low, high - 1)
+            }
+
+            #[inline]
+            fn new_inclusive<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
+            where
+                B1: SampleBorrow<Self::X> + Sized,
+                B2: SampleBorrow<Self::X> + Sized,
+            {
+                let low = *low_b.borrow();
+                let high = *high_b.borrow();
+
+                if !(low <= high) {
+                    return Err(Error::EmptyRange);
+                }
+                let unsigned_max = $u_large::MAX;
+
+                // `range == 0` means the full range of the type
+                let range = high.wrapping_sub(low).wrapping_add(1) as $unsigned;
+                let ints_to_reject = if range > 0 {
+                    let range = $u_large::from(range);
+                    (unsigned_max - range + 1) % range
+                } else {
+                    0
+                };
+
+                Ok(UniformInt {
+                    low,
+                    range: range as $ty,
+                    z: ints_to_reject as $unsigned as $ty,
+                })
+            }
+
+            #[inline]
+            fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Self::X {
+                let range = self.range as $unsigned as $u_large;
+                if range > 0 {
+                    // widening multiply: `hi` is uniform in `[0, range)` as
+                    // long as `lo` falls in the accepted zone
+                    let unsigned_max = $u_large::MAX;
+                    let zone = unsigned_max - (self.z as $unsigned as $u_large);
+                    loop {
+                        let v: $u_large = Standard.sample(rng);
+                        let (hi, lo) = v.wmul(range);
+                        if lo <= zone {
+                            return self.low.wrapping_add(hi as $ty);
+                        }
+                    }
+                } else {
+                    let v: $u_large = Standard.sample(rng);
+                    v as $ty
+                }
+            }
+
+            #[inline]
+            fn sample_single<R: RngCore + ?Sized, B1, B2>(low_b: B1, high_b: B2, rng: &mut R)
+            -> Result<Self::X, Error>
+            where
+                B1: SampleBorrow<Self::X> + Sized,
+                B2: SampleBorrow<Self::X> + Sized,
+            {
+                let low = *low_b.borrow();
+                let high = *high_b.borrow();
+                if !(low < high) {
+                    return Err(Error::EmptyRange);
+                }
+                Self::sample_single_inclusive(low, high - 1, rng)
+            }
+
+            #[inline]
+            fn sample_single_inclusive<R: RngCore + ?Sized, B1, B2>(low_b: B1, high_b: B2, rng: &mut R)
+            -> Result<Self::X, Error>
+            where
+                B1: SampleBorrow<Self::X> + Sized,
+                B2: SampleBorrow<Self::X> + Sized,
+            {
+                let low = *low_b.borrow();
+                let high = *high_b.borrow();
+                if !(low <= high) {
+                    return Err(Error::EmptyRange);
+                }
+                let range = high.wrapping_sub(low).wrapping_add(1) as $unsigned as $u_large;
+                if range == 0 {
+                    let v: $u_large = Standard.sample(rng);
+                    return Ok(v as $ty);
+                }
+
+                // for small types the exact zone is cheap to compute; for the
+                // others a conservative zone avoids the modulo
+                let zone = if $unsigned::MAX <= u16::MAX as $unsigned {
+                    let unsigned_max: $u_large = $u_large::MAX;
+                    let ints_to_reject = (unsigned_max - range + 1) % range;
+                    unsigned_max - ints_to_reject
+                } else {
+                    (range << range.leading_zeros()).wrapping_sub(1)
+                };
+
+                loop {
+                    let v: $u_large = Standard.sample(rng);
+                    let (hi, lo) = v.wmul(range);
+                    if lo <= zone {
+                        return Ok(low.wrapping_add(hi as $ty));
+                    }
+                }
+            }
+        }
+    };
+}
+
+uniform_int_impl! { i8, u8, u32 }
+uniform_int_impl! { i16, u16, u32 }
+uniform_int_impl! { i32, u32, u32 }
+uniform_int_impl! { i64, u64, u64 }
+uniform_int_impl! { i128, u128, u128 }
+uniform_int_impl! { isize, usize, usize }
+uniform_int_impl! { u8, u8, u32 }
+uniform_int_impl! { u16, u16, u32 }
+uniform_int_impl! { u32, u32, u32 }
+uniform_int_impl! { u64, u64, u64 }
+uniform_int_impl! { u128, u128, u128 }
+uniform_int_impl! { usize, usize, usize }
+
+/// Full-width multiplication, returning the `(high, low)` halves.
+trait WideningMultiply<RHS = Self> {
+    type Output;
+
+    fn wmul(self, x: RHS) -> Self::Output;
+}
+
+macro_rules! wmul_impl {
+    ($ty: ty, $wide: ty, $shift: expr) => {
+        impl WideningMultiply for $ty {
+            type Output = ($ty, $ty);
+
+            #[inline(always)]
+            fn wmul(self, x: $ty) -> Self::Output {
+                let tmp = (self as $wide) * (x as $wide);
+                ((tmp >> $shift) as $ty, tmp as $ty)
+            }
+        }
+    };
+}
+
+wmul_impl! { u32, u64, 32 }
+wmul_impl! { u64, u128, 64 }
+
+impl WideningMultiply for u128 {
+    type Output = (u128, u128);
+
+    #[inline(always)]
+    fn wmul(self, b: u128) -> Self::Output {
+        // schoolbook multiplication on 64-bit halves
+        const LOWER_MASK: u128 = !0 >> 64;
+        let mut low = (self & LOWER_MASK).wrapping_mul(b & LOWER_MASK);
+        let mut t = low >> 64;
+        low &= LOWER_MASK;
+        t += (self >> 64).wrapping_mul(b & LOWER_MASK);
+        low += (t & LOWER_MASK) << 64;
+        let mut high = t >> 64;
+        t = low >> 64;
+        low &= LOWER_MASK;
+        t += (b >> 64).wrapping_mul(self & LOWER_MASK);
+        low += (t & LOWER_MASK) << 64;
+        high += t >> 64;
+        high += (self >> 64).wrapping_mul(b >> 64);
+
+        (high, low)
+    }
+}
+
+macro_rules! wmul_impl_usize {
+    ($ty: ty) => {
+        impl WideningMultiply for usize {
+            type Output = (usize, usize);
+
+            #[inline(always)]
+            fn wmul(self, x: usize) -> Self::Output {
+                let (high, low) = (self as $ty).wmul(x as $ty);
+                (high as usize, low as usize)
+            }
+        }
+    };
+}
+
+#[cfg(target_pointer_width = "32")]
+wmul_impl_usize! { u32 }
+#[cfg(target_pointer_width = "64")]
+wmul_impl_usize! { u64 }
+
+impl SampleUniform for char {
+    type Sampler = UniformChar;
+}
 
 #[derive(Clone, Copy, Debug)]
-#[cfg_attr(feature="serde1", derive(Serialize, Deserialize))]
+#[cfg_attr(feature="serde1", derive(Serialize))]
+#[cfg_attr(any(feature="serde1", feature="candid"), derive(Deserialize))]
+#[cfg_attr(any(feature="serde1", feature="candid"), serde(try_from = "UniformCharRecord"))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
 pub struct UniformChar {
     sampler: UniformInt<u32>,
 }
 
+// the encoded form of `UniformChar`; decoding checks it, since `sample`
+// relies on the range holding only compressed scalar values
+#[cfg(any(feature="serde1", feature="candid"))]
+#[derive(Deserialize)]
+struct UniformCharRecord {
+    sampler: UniformInt<u32>,
+}
+
+#[cfg(any(feature="serde1", feature="candid"))]
+impl TryFrom<UniformCharRecord> for UniformChar {
+    type Error = Error;
+
+    fn try_from(record: UniformCharRecord) -> Result<Self, Error> {
+        let UniformInt { low, range, .. } = record.sampler;
+        // a zero range is the full `u32` range
+        let high = range
+            .checked_sub(1)
+            .and_then(|span| low.checked_add(span))
+            .filter(|&high| high <= char_to_comp_u32(char::MAX))
+            .ok_or(Error::InvalidBounds)?;
+        // rebuilt rather than trusted, so the rejection zone is recomputed
+        let sampler = UniformInt::<u32>::new_inclusive(low, high)?;
+        Ok(UniformChar { sampler })
+    }
+}
+
 // UTF-16 surrogate range start
 const CHAR_SURROGATE_START: u32 = 0xD800;
 // UTF-16 surrogate range size
@@ -256,129 +483,427 @@ fn char_to_comp_u32(c: char) -> u32 {
     }
 }
 
-// impl UniformSampler for UniformChar {
-//     type X = char;
-//     #[inline]
-//     fn new<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
-//     where
-//         B1: SampleBorrow<Self::X> +  Sized,
-//         B2: SampleBorrow<Self::X> + Sized,
-//     {
-//         let low = char_to_comp_u32(*low_b.borrow());
-//         let high = char_to_comp_u32(*high_b.borrow());
-//         let sampler = UniformInt::<u32>::new(low, high);
-//         sampler.map(|sampler| UniformChar { sampler })
-//     }
-
-//     #[inline]
-//     fn new_inclusive<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error> 
-//         where
-//             B1: SampleBorrow<Self::X> + Sized,
-//             B2: SampleBorrow<Self::X> + Sized 
-//     {
-//         let low = char_to_comp_u32(*low_b.borrow());
-//         let high = char_to_comp_u32(*high_b.borrow());
-//         let sampler = UniformInt::<u32>::new_inclusive(low, high);
-//         sampler.map(|sampler| UniformChar { sampler })
-//     }
-
-//     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Self::X {
-//         let mut x=  self.sampler.sample(rng);
-//         if x >= CHAR_SURROGATE_START {
-//             x += CHAR_SURROGATE_LEN;
-//         }
-
-//         unsafe { core::char::from_u32_unchecked(x)}
-//     }
-// }
+impl UniformSampler for UniformChar {
+    type X = char;
+    #[inline]
+    fn new<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
+    where
+        B1: SampleBorrow<Self::X> + Sized,
+        B2: SampleBorrow<Self::X> + Sized,
+    {
+        let low = char_to_comp_u32(*low_b.borrow());
+        let high = char_to_comp_u32(*high_b.borrow());
+        let sampler = UniformInt::<u32>::new(low, high);
+        sampler.map(|sampler| UniformChar { sampler })
+    }
+
+    #[inline]
+    fn new_inclusive<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
+    where
+        B1: SampleBorrow<Self::X> + Sized,
+        B2: SampleBorrow<Self::X> + Sized,
+    {
+        let low = char_to_comp_u32(*low_b.borrow());
+        let high = char_to_comp_u32(*high_b.borrow());
+        let sampler = UniformInt::<u32>::new_inclusive(low, high);
+        sampler.map(|sampler| UniformChar { sampler })
+    }
+
+    #[inline]
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Self::X {
+        let mut x = self.sampler.sample(rng);
+        if x >= CHAR_SURROGATE_START {
+            x += CHAR_SURROGATE_LEN;
+        }
+
+        // safety: `x` was a compressed scalar value, and expanding it back
+        // skips the surrogate range. the constructors and decoding only
+        // accept ranges of compressed scalar values
+        unsafe { core::char::from_u32_unchecked(x) }
+    }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq)]
-#[cfg_attr(feature="serde1", derive(Serialize, Deserialize))]
+#[cfg_attr(feature="serde1", derive(Serialize))]
+#[cfg_attr(any(feature="serde1", feature="candid"), derive(Deserialize))]
+#[cfg_attr(any(feature="serde1", feature="candid"), serde(try_from = "UniformFloatRecord<X>"))]
+#[cfg_attr(any(feature="serde1", feature="candid"), serde(bound(deserialize = "X: Deserialize<'de>, UniformFloat<X>: TryFrom<UniformFloatRecord<X>, Error = Error>")))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
 pub struct UniformFloat<X> {
     low: X,
     scale: X,
 }
 
-// macro_rules! uniform_float_impl {
-//     ($ty: ty, $uty: ident, $f_scalar: ident, $u_scalar: ident, $bits_to_discard: expr) => {
-//         impl SampleUniform for $ty {
-//             type Sampler = UniformFloat<$ty>;
-//         }
-
-//         impl UniformSampler for UniformFloat<$ty> {
-//             type X = $ty;
-
-//             fn new<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
-//             where 
-//                 B1: SampleBorrow<Self::X> + Sized,
-//                 B2: SampleBorrow<Self::X> + Sized,
-//             {
-//                 let low = *low_b.borrow();
-//                 let high = *high_b.borrow();
-//                 #[cfg(debug_assertions)]
-//                 if !(low.all_finite()) || !(high.all_finite()) {
-//                     return Err(Error::NonFinite);
-//                 }
-
-//                 if !(low.all_lt(high)) {
-//                     return Err(Error::EmptyRange);
-//                 }
-
-//                 let max_rand = <$ty>::splat(
-//                     (::core::$u_scalar::MAX >> $bits_to_discard).into_float_with_exponent(0) - 1.0,
-//                 );
-//                 let mut scale = high -  low;
-//                 if !(scale.all_finite()) {
-//                     return Err(Error::EmptyRange);
-//                 }
-
-//                 loop {
-//                     let mask = (scale * max_rand + low).ge_mask(high);
-//                     if !mask.any() {
-//                         break;
-//                     }
-//                     scale = scale.decrease_masked(mask);
-//                 }
-//                 debug_assert!(<$ty>::splat(0.0).all_le(scale));
-//                 Ok(UniformFloat { low, scale })
-//             }
-
-//             fn new_inclusive<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
-//             where
-//                 B1: SampleBorrow<Self::X> + Sized,
-//                 B2: SampleBorrow<Self::X> + Sized,
-//             {
-//                 let low = *low_b.borrow();
-//                 let high = *high_b.borrow();
-//                 #[cfg(debug_assertions)]
-//                 if !(low.all_finite()) || !(high.all_finite()) {
-//                     return Err(Error::NonFinite);
-//                 }
-//                 if !low.all_le(high) {
-//                     return Err(Error::EmptyRange);
-//                 }
-//                 let max_rand = <$ty>::splat(
-//                     (::core::$u_scalar::MAX >> $bits_to_discard).into_float_with_exponent(0) - 1.0,
-//                 );
-
-//                 let mut scale = (high - low) / max_rand;
-//                 if !scale.all_finite() {
-//                     return Err(Error::NonFinite);
-//                 }
-
-//                 loop {
-//                     let mask = (scale * max_rand + low).gt_mask(high);
-//                     if !mask.any() {
-//                         break;
-//                     }
-//                     scale = scale.decrease_masked(mask);
-//                 }
-//                 debug_assert!(<$ty>::splat(0.0).all_le(scale));
-//                 Ok(UniformFloat { low, scale })
-//             }
-//         }
-//     };
-// }
-
-// uniform_float_impl!( f32, u32, f32, u32, 32 - 23);
-// uniform_float_impl!( f64, u64, f64, u64, 64 - 52);
\ No newline at end of file
+// the encoded form of `UniformFloat`; `high` is not stored, so decoding
+// cannot rebuild the sampler but checks that every sample is finite and
+// at least `low`, as the constructors guarantee
+#[cfg(any(feature="serde1", feature="candid"))]
+#[derive(Deserialize)]
+struct UniformFloatRecord<X> {
+    low: X,
+    scale: X,
+}
+
+macro_rules! uniform_float_impl {
+    ($ty: ty, $uty: ident, $bits_to_discard: expr) => {
+        impl SampleUniform for $ty {
+            type Sampler = UniformFloat<$ty>;
+        }
+
+        #[cfg(any(feature="serde1", feature="candid"))]
+        impl TryFrom<UniformFloatRecord<$ty>> for UniformFloat<$ty> {
+            type Error = Error;
+
+            fn try_from(record: UniformFloatRecord<$ty>) -> Result<Self, Error> {
+                let UniformFloatRecord { low, scale } = record;
+                let max_rand = ($uty::MAX >> $bits_to_discard).into_float_with_exponent(0) - 1.0;
+                if !(low.is_finite() && (scale * max_rand + low).is_finite()) {
+                    return Err(Error::NonFinite);
+                }
+                if !(scale >= 0.0) {
+                    return Err(Error::EmptyRange);
+                }
+                Ok(UniformFloat { low, scale })
+            }
+        }
+
+        impl UniformSampler for UniformFloat<$ty> {
+            type X = $ty;
+
+            fn new<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
+            where
+                B1: SampleBorrow<Self::X> + Sized,
+                B2: SampleBorrow<Self::X> + Sized,
+            {
+                let low = *low_b.borrow();
+                let high = *high_b.borrow();
+                if !(low.is_finite() && high.is_finite()) {
+                    return Err(Error::NonFinite);
+                }
+                if !(low < high) {
+                    return Err(Error::EmptyRange);
+                }
+
+                let max_rand = ($uty::MAX >> $bits_to_discard).into_float_with_exponent(0) - 1.0;
+                let scale = high - low;
+                if !scale.is_finite() {
+                    return Err(Error::NonFinite);
+                }
+
+                // rounding in `value * scale + low` may reach `high`; shrink
+                // the scale until the largest sample stays below it
+                let scale = scale.largest_fitting(|scale| scale * max_rand + low < high);
+                debug_assert!(0.0 <= scale);
+                Ok(UniformFloat { low, scale })
+            }
+
+            fn new_inclusive<B1, B2>(low_b: B1, high_b: B2) -> Result<Self, Error>
+            where
+                B1: SampleBorrow<Self::X> + Sized,
+                B2: SampleBorrow<Self::X> + Sized,
+            {
+                let low = *low_b.borrow();
+                let high = *high_b.borrow();
+                if !(low.is_finite() && high.is_finite()) {
+                    return Err(Error::NonFinite);
+                }
+                if !(low <= high) {
+                    return Err(Error::EmptyRange);
+                }
+
+                let max_rand = ($uty::MAX >> $bits_to_discard).into_float_with_exponent(0) - 1.0;
+                // scale so that the largest sample lands exactly on `high`
+                let scale = (high - low) / max_rand;
+                if !scale.is_finite() {
+                    return Err(Error::NonFinite);
+                }
+
+                let scale = scale.largest_fitting(|scale| scale * max_rand + low <= high);
+                debug_assert!(0.0 <= scale);
+                Ok(UniformFloat { low, scale })
+            }
+
+            fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> Self::X {
+                // a value in `[1, 2)`, shifted down to `[0, 1)`
+                let v: $uty = Standard.sample(rng);
+                let value0_1 = (v >> $bits_to_discard).into_float_with_exponent(0) - 1.0;
+                value0_1 * self.scale + self.low
+            }
+
+            fn sample_single<R: RngCore + ?Sized, B1, B2>(low_b: B1, high_b: B2, rng: &mut R)
+            -> Result<Self::X, Error>
+            where
+                B1: SampleBorrow<Self::X> + Sized,
+                B2: SampleBorrow<Self::X> + Sized,
+            {
+                let low = *low_b.borrow();
+                let high = *high_b.borrow();
+                if !(low.is_finite() && high.is_finite()) {
+                    return Err(Error::NonFinite);
+                }
+                if !(low < high) {
+                    return Err(Error::EmptyRange);
+                }
+                let mut scale = high - low;
+                if !scale.is_finite() {
+                    return Err(Error::NonFinite);
+                }
+
+                loop {
+                    let v: $uty = Standard.sample(rng);
+                    let value0_1 = (v >> $bits_to_discard).into_float_with_exponent(0) - 1.0;
+                    let res = value0_1 * scale + low;
+                    if res < high {
+                        return Ok(res);
+                    }
+                    // rounding reached `high`; shrink the scale like `new`
+                    // does and try again
+                    scale = scale.decrease();
+                }
+            }
+        }
+
+        impl FloatDecrease for $ty {
+            #[inline]
+            fn decrease(self) -> $ty {
+                // for a positive float, one less in the bits is one ulp less
+                <$ty>::from_bits(self.to_bits().saturating_sub(1))
+            }
+
+            fn largest_fitting<F: Fn($ty) -> bool>(self, fits: F) -> $ty {
+                if fits(self) {
+                    return self;
+                }
+                // zero fits and `self` does not; positive floats order like
+                // their bits, so bisect on those
+                let (mut lo, mut hi): ($uty, $uty) = (0, self.to_bits());
+                while hi - lo > 1 {
+                    let mid = lo + (hi - lo) / 2;
+                    if fits(<$ty>::from_bits(mid)) {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                <$ty>::from_bits(lo)
+            }
+        }
+    };
+}
+
+/// Shrink a non-negative float scale towards zero, one ulp at a time, so
+/// the largest sample stays as close to `high` as possible.
+trait FloatDecrease: Sized {
+    /// One ulp less.
+    fn decrease(self) -> Self;
+
+    /// The value `decrease` would stop at if called until `fits` holds,
+    /// without taking one step per ulp: a range a few ulps wide next to a
+    /// large `low` would otherwise need up to 2^51 steps. `fits` must hold
+    /// for zero, and for every value below any value it holds for.
+    fn largest_fitting<F: Fn(Self) -> bool>(self, fits: F) -> Self;
+}
+
+uniform_float_impl! { f32, u32, 32 - 23 }
+uniform_float_impl! { f64, u64, 64 - 52 }
+
+/// Build a float from mantissa bits and an exponent.
+pub(crate) trait IntoFloat {
+    type F;
+
+    /// Use the low mantissa-sized bits of `self` as the fraction and
+    /// `exponent` as the unbiased exponent, so an exponent of `0` gives a
+    /// value in `[1, 2)`.
+    fn into_float_with_exponent(self, exponent: i32) -> Self::F;
+}
+
+macro_rules! into_float_impl {
+    ($uty: ty, $f: ty, $fraction_bits: expr, $exponent_bias: expr) => {
+        impl IntoFloat for $uty {
+            type F = $f;
+
+            #[inline(always)]
+            fn into_float_with_exponent(self, exponent: i32) -> $f {
+                let exponent_bits = (($exponent_bias + exponent) as $uty) << $fraction_bits;
+                <$f>::from_bits(self | exponent_bits)
+            }
+        }
+    };
+}
+
+into_float_impl! { u32, f32, 23, 127 }
+into_float_impl! { u64, f64, 52, 1023 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{Rand32, Rand64};
+    use crate::{RngExt, SeedableRng};
+
+    #[test]
+    fn integers_stay_in_range() {
+        let mut rng = Rand32::seed_from_u64(101);
+        macro_rules! t {
+            ($ty: ident) => {{
+                let v: &[($ty, $ty)] = &[
+                    ($ty::MIN, $ty::MAX),
+                    ($ty::MIN, $ty::MIN + 1),
+                    ($ty::MAX - 1, $ty::MAX),
+                    (10 as $ty, 100 as $ty),
+                ];
+                for &(low, high) in v {
+                    let exclusive = Uniform::new(low, high).unwrap();
+                    let inclusive = Uniform::new_inclusive(low, high).unwrap();
+                    for _ in 0..1000 {
+                        let x = exclusive.sample(&mut rng);
+                        assert!(low <= x && x < high);
+                        let x = inclusive.sample(&mut rng);
+                        assert!(low <= x && x <= high);
+                        let x = rng.gen_range(low..high);
+                        assert!(low <= x && x < high);
+                        let x = rng.gen_range(low..=high);
+                        assert!(low <= x && x <= high);
+                    }
+                }
+            }};
+        }
+        t!(i8);
+        t!(i16);
+        t!(i32);
+        t!(i64);
+        t!(i128);
+        t!(isize);
+        t!(u8);
+        t!(u16);
+        t!(u32);
+        t!(u64);
+        t!(u128);
+        t!(usize);
+    }
+
+    #[test]
+    fn empty_ranges_are_errors() {
+        assert_eq!(Uniform::new(5u32, 5).unwrap_err(), Error::EmptyRange);
+        assert_eq!(Uniform::new_inclusive(6i64, 5).unwrap_err(), Error::EmptyRange);
+        let mut rng = Rand64::seed_from_u64(0);
+        assert!((3u8..3).sample_single(&mut rng).is_err());
+        assert_eq!((3u8..=3).sample_single(&mut rng), Ok(3));
+    }
+
+    #[test]
+    fn dice_rolls_hit_every_face_evenly() {
+        let mut rng = Rand64::seed_from_u64(6);
+        let die = Uniform::new_inclusive(1usize, 6).unwrap();
+        let mut counts = [0u32; 7];
+        for _ in 0..60_000 {
+            counts[die.sample(&mut rng)] += 1;
+        }
+        assert_eq!(counts[0], 0);
+        for &c in &counts[1..] {
+            assert!((9_000..11_000).contains(&c), "{:?}", counts);
+        }
+    }
+
+    #[test]
+    fn floats_never_return_high() {
+        let mut rng = Rand32::seed_from_u64(3);
+        macro_rules! t {
+            ($ty: ident, $uty: ident, $discard: expr) => {{
+                let v: &[($ty, $ty)] = &[
+                    (0.0, 1.0),
+                    (-1e6, 1e6),
+                    (1.0, 1.0 + $ty::EPSILON),
+                    ($ty::MIN / 4.0, $ty::MAX / 4.0),
+                ];
+                let max_rand = ($uty::MAX >> $discard).into_float_with_exponent(0) - 1.0;
+                for &(low, high) in v {
+                    // a shrunk scale is the largest whose top sample is in
+                    // range: one ulp more would reach past it
+                    let next_up = |x: $ty| <$ty>::from_bits(x.to_bits() + 1);
+                    let UniformFloat { scale, .. } = UniformFloat::<$ty>::new(low, high).unwrap();
+                    assert!(scale * max_rand + low < high);
+                    assert!(scale == high - low || next_up(scale) * max_rand + low >= high);
+                    let UniformFloat { scale, .. } = UniformFloat::<$ty>::new_inclusive(low, high).unwrap();
+                    assert!(scale * max_rand + low <= high);
+                    assert!(scale == (high - low) / max_rand || next_up(scale) * max_rand + low > high);
+
+                    let exclusive = Uniform::new(low, high).unwrap();
+                    let inclusive = Uniform::new_inclusive(low, high).unwrap();
+                    for _ in 0..1000 {
+                        let x = exclusive.sample(&mut rng);
+                        assert!(low <= x && x < high);
+                        let x = inclusive.sample(&mut rng);
+                        assert!(low <= x && x <= high);
+                        let x = rng.gen_range(low..high);
+                        assert!(low <= x && x < high);
+                    }
+                }
+
+                // a range two values wide can only ever return `low`
+                let low: $ty = 1.0;
+                let high = low + $ty::EPSILON;
+                let d = Uniform::new(low, high).unwrap();
+                for _ in 0..100 {
+                    assert_eq!(d.sample(&mut rng), low);
+                }
+                assert_eq!(Uniform::new_inclusive(low, low).unwrap().sample(&mut rng), low);
+            }};
+        }
+        t!(f32, u32, 32 - 23);
+        t!(f64, u64, 64 - 52);
+    }
+
+    #[test]
+    fn floats_reject_bad_ranges() {
+        assert_eq!(Uniform::new(0.0f64, f64::INFINITY).unwrap_err(), Error::NonFinite);
+        assert_eq!(Uniform::new(f32::NAN, 1.0).unwrap_err(), Error::NonFinite);
+        assert_eq!(Uniform::new(f64::MIN, f64::MAX).unwrap_err(), Error::NonFinite);
+        assert_eq!(Uniform::new_inclusive(0.0f32, f32::NAN).unwrap_err(), Error::NonFinite);
+        assert_eq!(Uniform::new(1.0f32, 1.0).unwrap_err(), Error::EmptyRange);
+        assert_eq!(Uniform::new_inclusive(2.0f64, 1.0).unwrap_err(), Error::EmptyRange);
+        let mut rng = Rand64::seed_from_u64(0);
+        assert_eq!((0.0..f64::INFINITY).sample_single(&mut rng), Err(Error::NonFinite));
+    }
+
+    #[test]
+    fn chars_skip_surrogates() {
+        let mut rng = Rand32::seed_from_u64(8);
+        let ranges: &[(char, char)] = &[
+            ('a', 'z'),
+            ('\u{4E00}', '\u{9FFF}'),
+            ('\u{1F600}', '\u{1F64F}'),
+            ('\u{D7FF}', '\u{E000}'),
+            ('\u{0}', char::MAX),
+        ];
+        for &(low, high) in ranges {
+            let exclusive = Uniform::new(low, high).unwrap();
+            let inclusive = Uniform::new_inclusive(low, high).unwrap();
+            for _ in 0..1000 {
+                let c = exclusive.sample(&mut rng);
+                assert!(low <= c && c < high);
+                let c = inclusive.sample(&mut rng);
+                assert!(low <= c && c <= high);
+            }
+        }
+
+        // only the two values on either side of the gap are possible
+        let d = Uniform::new_inclusive('\u{D7FF}', '\u{E000}').unwrap();
+        let mut seen = [false; 2];
+        for _ in 0..100 {
+            match d.sample(&mut rng) {
+                '\u{D7FF}' => seen[0] = true,
+                '\u{E000}' => seen[1] = true,
+                c => panic!("unexpected {:?}", c),
+            }
+        }
+        assert_eq!(seen, [true, true]);
+        assert_eq!(Uniform::new('b', 'a').unwrap_err(), Error::EmptyRange);
+        assert!(rng.gen_range('a'..='z').is_ascii_lowercase());
+    }
+
+    #[test]
+    fn widening_multiply_u128() {
+        assert_eq!(u128::MAX.wmul(u128::MAX), (u128::MAX - 1, 1));
+        assert_eq!((1u128 << 100).wmul(1 << 100), (1 << 72, 0));
+    }
+}
@@ -1,29 +1,68 @@
+use crate::core::{self as rng_core, Error, RngCore, SeedableRng};
+use crate::small::SplitMix64;
+#[cfg(any(feature = "serde1", feature = "candid"))]
+use crate::stable::StateError;
+use crate::uniform::{self, UniformFloat, UniformSampler};
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), derive(serde::Deserialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), serde(try_from = "Rand32Record"))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
 pub struct Rand32 {
     state: u64,
     inc: u64,
 }
 
+// the encoded form of `Rand32`; decoding rejects an even increment, as
+// `read_state` does
+#[cfg(any(feature = "serde1", feature = "candid"))]
+#[derive(serde::Deserialize)]
+struct Rand32Record {
+    state: u64,
+    inc: u64,
+}
+
+#[cfg(any(feature = "serde1", feature = "candid"))]
+impl TryFrom<Rand32Record> for Rand32 {
+    type Error = StateError;
+
+    fn try_from(record: Rand32Record) -> Result<Self, StateError> {
+        let Rand32Record { state, inc } = record;
+        if inc & 1 == 0 {
+            return Err(StateError::InvalidState);
+        }
+        Ok(Self { state, inc })
+    }
+}
+
 impl Rand32 {
     ///the default value for `increment`
     /// this is basically arbitrary, it comes from the
     /// PCG reference C implementation;
     pub const DEFAULT_INC: u64 = 1442695040888963407;
-    
-    ///this is the number that you have to really get right 
+
+    ///this is the number that you have to really get right
     ///the value used here is from the PCG C implementation
     pub(crate) const MULTIPLIER: u64 = 6364136223846793005;
 
     ///creates a new PRNG with a given seed and a default increment
+    ///
+    /// the seed is expanded with `SplitMix64` into the starting state,
+    /// so nearby seeds start at unrelated points of the sequence. this
+    /// is not the same state as `new_inc(seed, Self::DEFAULT_INC)`
     pub fn new(seed: u64) -> Self {
-        Self::new_inc(seed, Self::DEFAULT_INC)
+        Self {
+            state: SplitMix64::new(seed).next_u64(),
+            inc: Self::DEFAULT_INC.wrapping_shl(1) | 1,
+        }
     }
 
     ///create a new PRNG. the two inputs, `seed` and `increment`
     /// determine what you get; `increment` basically selects which
-    /// sequence of all those possible the PRNG will produce, and 
+    /// sequence of all those possible the PRNG will produce, and
     /// the `seed` selects where in the sequence youstart
-    /// 
+    ///
     /// both are arbitrary; increment must be an odd number but this
     /// handles that for you
     pub fn new_inc(seed: u64, increment: u64) -> Self {
@@ -38,90 +77,42 @@ impl Rand32 {
         rng
     }
 
-    // /returns the internal state of the PRNG. this alllow
-    // / you to save a PRNG and create a new one that will 
-    // / resume from the same spot in the sequence
-    // pub fn state(&self) -> (u64, u64) {
-    //     (self.state, self.state)
-    // }
-
-    // /createa a new PRNG from a saved state from 
-    // / Rand32::state() 
-    // / this is NOT quite the same as `new_inc` because
-    // /`new_inc()` does a little extra setup work to
-    // / initialize the state
-
-    // pub fn from_state(state: (u64, u64)) -> Self {
-    //     let (state, inc) = state;
-    //     Self { state, inc }
-    // }
-
-    //produces a random `u32` in the range
-    // `[0, u32::MAX]`
-    // pub fn rand_u32(&mut self) -> u32 {
-    //     let oldstate = self.state;
-    //     self.state = oldstate
-    //     .wrapping_mul(Self::MULTIPLIER)
-    //     .wrapping_add(self.inc);
-
-    //     let xorshifted = (((oldstate >> 18) ^ oldstate) >> 27) as u32;
-    //     let rot = (oldstate >> 59) as u32;
-    //     xorshifted.rotate_right(rot)
-    // }
-
-    //produces a random `i32` in the range `[i32::MIN, i32::MAX]`
-    //
-    // pub fn rand_i32(&mut self) -> i32 {
-    //     self.rand_u32() as i32
-    // }
-
-    // pub fn rand_f32(&mut self) -> f32 {
-    //     // This impl was taken more or less from `rand`, see
-    //     // <https://docs.rs/rand/0.7.0/src/rand/distributions/float.rs.html#104-117>
-    //     // There MAY be better ways to do this, see:
-    //     // https://mumble.net/~campbell/2014/04/28/uniform-random-float
-    //     // https://mumble.net/~campbell/2014/04/28/random_real.c
-    //     // https://github.com/Lokathor/randomize/issues/34
-    //     const TOTAL_BITS: u32 = 32;
-    //     const PRECISION: u32 = std::f32::MANTISSA_DIGITS + 1;
-    //     const MANTISSA_SCALE: f32 = 1.0 / ((1u32 << PRECISION) as f32);
-    //     let mut u = self.rand_u32();
-    //     u >>= TOTAL_BITS - PRECISION;
-    //     u as f32 * MANTISSA_SCALE
-    // }
-
-    // pub fn gen_range(&mut self, low: f32, high: f32) -> f32 {
-    //     let r = self.rand_f32();
-    //     let result = low + (high - low) * r;
-    //     result
-    // }
-}
-
-pub trait Rng {
-    fn state(&self) -> (u64, u64);
-
-    fn from_state(state: (u64, u64)) -> Self;
-
-    fn rand_u32(&mut self) -> u32;
-
-    fn rand_i32(&mut self) -> i32;
-
-    fn rand_f32(&mut self) -> f32;
+    ///returns the internal state of the PRNG. this alllow
+    /// you to save a PRNG and create a new one that will
+    /// resume from the same spot in the sequence
+    pub fn state(&self) -> (u64, u64) {
+        (self.state, self.inc)
+    }
 
-    fn gen_range(&mut self, low: f32, high: f32) -> f32;
-}
+    ///createa a new PRNG from a saved state from
+    /// Rand32::state()
+    /// this is NOT quite the same as `new_inc` because
+    ///`new_inc()` does a little extra setup work to
+    /// initialize the state
+    pub fn from_state(state: (u64, u64)) -> Self {
+        let (state, inc) = state;
+        Self { state, inc }
+    }
 
-impl Rng for Rand32 {
-    fn state(&self) -> (u64, u64) {
-        (self.state, self.state)
+    ///returns the internal state as 16 bytes: `state` then `inc`, both
+    /// little-endian, see `Rand64::to_le_bytes()`
+    pub fn to_le_bytes(&self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.state.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.inc.to_le_bytes());
+        bytes
     }
 
-    fn from_state(state: (u64, u64)) -> Self {
-        let (state, inc) = state;
+    ///creates a PRNG from bytes produced by `Rand32::to_le_bytes()`
+    pub fn from_le_bytes(bytes: [u8; 16]) -> Self {
+        let state = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let inc = u64::from_le_bytes(bytes[8..].try_into().unwrap());
         Self { state, inc }
     }
 
-    fn rand_u32(&mut self) -> u32 {
+    ///produces a random `u32` in the range
+    /// `[0, u32::MAX]`
+    pub fn rand_u32(&mut self) -> u32 {
         let oldstate = self.state;
         self.state = oldstate
         .wrapping_mul(Self::MULTIPLIER)
@@ -132,12 +123,13 @@ impl Rng for Rand32 {
         xorshifted.rotate_right(rot)
     }
 
-    fn rand_i32(&mut self) -> i32 {
+    ///produces a random `i32` in the range `[i32::MIN, i32::MAX]`
+    pub fn rand_i32(&mut self) -> i32 {
         self.rand_u32() as i32
     }
 
-
-    fn rand_f32(&mut self) -> f32 {
+    ///produces a random `f32` in the range `[0.0, 1.0)`
+    pub fn rand_f32(&mut self) -> f32 {
         // This impl was taken more or less from `rand`, see
         // <https://docs.rs/rand/0.7.0/src/rand/distributions/float.rs.html#104-117>
         // There MAY be better ways to do this, see:
@@ -145,33 +137,100 @@ impl Rng for Rand32 {
         // https://mumble.net/~campbell/2014/04/28/random_real.c
         // https://github.com/Lokathor/randomize/issues/34
         const TOTAL_BITS: u32 = 32;
-        const PRECISION: u32 = std::f32::MANTISSA_DIGITS + 1;
+        const PRECISION: u32 = f32::MANTISSA_DIGITS + 1;
         const MANTISSA_SCALE: f32 = 1.0 / ((1u32 << PRECISION) as f32);
         let mut u = self.rand_u32();
         u >>= TOTAL_BITS - PRECISION;
         u as f32 * MANTISSA_SCALE
     }
+}
 
-    fn gen_range(&mut self, low: f32, high: f32) -> f32 {
-        let r = self.rand_f32();
-        let result = low + (high - low) * r;
-        result
+impl RngCore for Rand32 {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        self.rand_u32()
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        rng_core::next_u64_via_u32(self)
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rng_core::fill_bytes_via_next_u32(self, dest)
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
     }
 }
 
+impl SeedableRng for Rand32 {
+    /// the first 8 bytes are the seed, the last 8 bytes the increment,
+    /// both little-endian and passed to `new_inc`
+    type Seed = [u8; 16];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        let mut words = [0u64; 2];
+        rng_core::read_u64_into(&seed, &mut words);
+        Self::new_inc(words[0], words[1])
+    }
+}
+
+///a PCG with 128 bits of state. the output function is not the
+/// reference XSL-RR, so the values differ from other PCG64
+/// implementations, see the `pcg` module
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), derive(serde::Deserialize))]
+#[cfg_attr(any(feature = "serde1", feature = "candid"), serde(try_from = "Rand64Record"))]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
 pub struct  Rand64 {
     state: u128,
     inc: u128,
 }
 
+// the encoded form of `Rand64`; decoding rejects an even increment, as
+// `read_state` does
+#[cfg(any(feature = "serde1", feature = "candid"))]
+#[derive(serde::Deserialize)]
+struct Rand64Record {
+    state: u128,
+    inc: u128,
+}
+
+#[cfg(any(feature = "serde1", feature = "candid"))]
+impl TryFrom<Rand64Record> for Rand64 {
+    type Error = StateError;
+
+    fn try_from(record: Rand64Record) -> Result<Self, StateError> {
+        let Rand64Record { state, inc } = record;
+        if inc & 1 == 0 {
+            return Err(StateError::InvalidState);
+        }
+        Ok(Self { state, inc })
+    }
+}
+
 impl Rand64 {
     pub const DEFAULT_INC: u128 = 0x2FE0E169_FFBD06E3_5BC307BD_4D2F814F;
 
     pub(crate) const MULTIPLIER: u128 = 47026247687942121848144207491837523525;
 
+    ///creates a new PRNG with a given seed and a default increment,
+    /// expanding the seed with `SplitMix64`, see `Rand32::new()`
     pub fn new(seed: u128) -> Self {
-        Self::new_inc(seed, Self::DEFAULT_INC)
+        // each half goes through one SplitMix64 output, which is a
+        // bijection, so distinct seeds still give distinct states
+        let lo = SplitMix64::new(seed as u64).next_u64();
+        let hi = SplitMix64::new((seed >> 64) as u64 ^ lo).next_u64();
+        Self {
+            state: ((hi as u128) << 64) | lo as u128,
+            inc: Self::DEFAULT_INC.wrapping_shl(1) | 1,
+        }
     }
 
     pub fn new_inc(seed: u128, increment: u128) -> Self {
@@ -186,33 +245,61 @@ impl Rand64 {
         rng
     }
 
+    ///returns the internal state of the PRNG, see `Rand32::state()`
     pub fn state(&self) -> (u128, u128) {
         (self.state, self.inc)
     }
 
+    ///creates a PRNG from a saved state from `Rand64::state()`
     pub fn from_state(state: (u128, u128)) -> Self {
         let (state, inc) = state;
         Self { state, inc }
     }
 
+    ///returns the internal state as 32 bytes: `state` then `inc`, both
+    /// little-endian. unlike the `(u128, u128)` tuple this has the same
+    /// layout on every platform, so it can be stored and read back later
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bytes[..16].copy_from_slice(&self.state.to_le_bytes());
+        bytes[16..].copy_from_slice(&self.inc.to_le_bytes());
+        bytes
+    }
+
+    ///creates a PRNG from bytes produced by `Rand64::to_le_bytes()`
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+        let state = u128::from_le_bytes(bytes[..16].try_into().unwrap());
+        let inc = u128::from_le_bytes(bytes[16..].try_into().unwrap());
+        Self { state, inc }
+    }
+
     pub fn rand_u64(&mut self) -> u64 {
         let oldstate: u128 = self.state;
         self.state = oldstate
         .wrapping_mul(Self::MULTIPLIER)
         .wrapping_add(self.inc);
-        
+
         let xorshifted: u64 = (((oldstate >> 29) ^ oldstate) >> 58) as u64;
         let rot: u32 = (oldstate >> 122) as u32;
         xorshifted.rotate_right(rot)
     }
 
     pub fn rand_i64(&mut self) -> i64 {
-        self.rand_u64() as i64 
+        self.rand_u64() as i64
+    }
+
+    ///produces a random `u32` from the upper half of `rand_u64()`
+    pub fn rand_u32(&mut self) -> u32 {
+        (self.rand_u64() >> 32) as u32
+    }
+
+    pub fn rand_i32(&mut self) -> i32 {
+        self.rand_u32() as i32
     }
 
     pub fn rand_f64(&mut self) -> f64 {
         const TOTAL_BITS: u32 = 64;
-        const PRECISION: u32 = core::f64::MANTISSA_DIGITS + 1;
+        const PRECISION: u32 = f64::MANTISSA_DIGITS + 1;
         const MANTISSA_SCALE: f64 = 1.0 / ((1u64 << PRECISION) as f64);
 
         let mut u = self.rand_u64();
@@ -220,5 +307,287 @@ impl Rand64 {
         u as f64 * MANTISSA_SCALE
     }
 
-    
-}
\ No newline at end of file
+    ///produces a random `f64` in the range `[low, high)`
+    ///
+    /// `high` is never returned, even when rounding would produce it
+    pub fn rand_f64_range(&mut self, low: f64, high: f64) -> Result<f64, uniform::Error> {
+        UniformFloat::<f64>::sample_single(low, high, self)
+    }
+}
+
+impl RngCore for Rand64 {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        self.rand_u32()
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        self.rand_u64()
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rng_core::fill_bytes_via_next_u64(self, dest)
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for Rand64 {
+    /// the first 16 bytes are the seed, the last 16 bytes the increment,
+    /// both little-endian and passed to `new_inc`
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        let seed_part = u128::from_le_bytes(seed[..16].try_into().unwrap());
+        let inc = u128::from_le_bytes(seed[16..].try_into().unwrap());
+        Self::new_inc(seed_part, inc)
+    }
+}
+
+/// jump-ahead, distance and stream splitting for a PCG generator whose
+/// LCG state and increment are `$ty`
+macro_rules! pcg_jump_impl {
+    ($name:ident, $ty:ty, $next:ident) => {
+        impl $name {
+            ///moves the generator `delta` steps forward in its sequence,
+            /// as if `delta` outputs had been drawn, in O(log delta) time
+            ///
+            /// uses Brown's algorithm, "Random Number Generation with
+            /// Arbitrary Stride" (1994)
+            pub fn advance(&mut self, delta: $ty) {
+                let mut acc_mult: $ty = 1;
+                let mut acc_plus: $ty = 0;
+                let mut cur_mult = Self::MULTIPLIER;
+                let mut cur_plus = self.inc;
+                let mut delta = delta;
+                while delta > 0 {
+                    if delta & 1 == 1 {
+                        acc_mult = acc_mult.wrapping_mul(cur_mult);
+                        acc_plus = acc_plus.wrapping_mul(cur_mult).wrapping_add(cur_plus);
+                    }
+                    cur_plus = cur_mult.wrapping_add(1).wrapping_mul(cur_plus);
+                    cur_mult = cur_mult.wrapping_mul(cur_mult);
+                    delta >>= 1;
+                }
+                self.state = acc_mult.wrapping_mul(self.state).wrapping_add(acc_plus);
+            }
+
+            ///moves the generator `delta` steps back, undoing `advance(delta)`
+            /// or `delta` draws
+            pub fn backstep(&mut self, delta: $ty) {
+                // the sequence has period 2^N, so going back is going forward
+                self.advance(delta.wrapping_neg())
+            }
+
+            ///returns how many steps `self` must be advanced to reach
+            /// `other`, or `None` if they are on different streams
+            ///
+            /// an even increment, only reachable through `from_state` or
+            /// `from_le_bytes`, does not give a full-period sequence, so
+            /// there may be no distance and `None` is returned
+            pub fn distance(&self, other: &Self) -> Option<$ty> {
+                if self.inc != other.inc || self.inc & 1 == 0 {
+                    return None;
+                }
+                let mut cur_mult = Self::MULTIPLIER;
+                let mut cur_plus = self.inc;
+                let mut cur_state = self.state;
+                let mut the_bit: $ty = 1;
+                let mut distance: $ty = 0;
+                while cur_state != other.state {
+                    if (cur_state & the_bit) != (other.state & the_bit) {
+                        cur_state = cur_state.wrapping_mul(cur_mult).wrapping_add(cur_plus);
+                        distance |= the_bit;
+                    }
+                    the_bit <<= 1;
+                    cur_plus = cur_mult.wrapping_add(1).wrapping_mul(cur_plus);
+                    cur_mult = cur_mult.wrapping_mul(cur_mult);
+                }
+                Some(distance)
+            }
+
+            ///returns a child generator seeded from this one's output, on a
+            /// stream different from this one's
+            ///
+            /// the parent advances, so splitting again gives another child
+            pub fn split(&mut self) -> Self {
+                let seed: $ty = self.$next();
+                let mut stream: $ty = self.$next();
+                if (stream.wrapping_shl(1) | 1) == self.inc {
+                    stream ^= 1;
+                }
+                Self::new_inc(seed, stream)
+            }
+        }
+    };
+}
+
+impl Rand64 {
+    fn next_u128(&mut self) -> u128 {
+        (u128::from(self.rand_u64()) << 64) | u128::from(self.rand_u64())
+    }
+}
+
+pcg_jump_impl!(Rand32, u64, next_u64);
+pcg_jump_impl!(Rand64, u128, next_u128);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_seed_matches_new_inc() {
+        let mut seed = [0u8; 16];
+        seed[..8].copy_from_slice(&42u64.to_le_bytes());
+        seed[8..].copy_from_slice(&54u64.to_le_bytes());
+        assert_eq!(Rand32::from_seed(seed), Rand32::new_inc(42, 54));
+
+        let mut seed = [0u8; 32];
+        seed[..16].copy_from_slice(&42u128.to_le_bytes());
+        seed[16..].copy_from_slice(&54u128.to_le_bytes());
+        assert_eq!(Rand64::from_seed(seed), Rand64::new_inc(42, 54));
+    }
+
+    #[test]
+    fn seed_from_u64_mixes_small_seeds() {
+        let a = Rand32::seed_from_u64(0);
+        let b = Rand32::seed_from_u64(1);
+        assert_ne!(a.state(), b.state());
+        assert_ne!(a.state().1, b.state().1);
+        assert_eq!(Rand64::seed_from_u64(9), Rand64::seed_from_u64(9));
+    }
+
+    #[test]
+    fn new_expands_seed_with_splitmix() {
+        let (state, inc) = Rand32::new(1).state();
+        assert_eq!(state, SplitMix64::new(1).next_u64());
+        assert_eq!(inc, Rand32::new_inc(1, Rand32::DEFAULT_INC).state().1);
+
+        // the high half is mixed too, even for small seeds
+        let a = Rand64::new(1).state().0;
+        let b = Rand64::new(2).state().0;
+        assert_ne!(a >> 64, b >> 64);
+        assert_ne!(Rand64::new(1 << 64), Rand64::new(1));
+    }
+
+    #[test]
+    fn rand64_state_round_trips() {
+        let mut rng = Rand64::seed_from_u64(77);
+        let _ = rng.rand_u64();
+        let saved = rng.to_le_bytes();
+        let mut restored = Rand64::from_le_bytes(saved);
+        assert_eq!(restored, rng);
+        assert_eq!(Rand64::from_state(rng.state()), rng);
+        for _ in 0..10 {
+            assert_eq!(restored.rand_u64(), rng.rand_u64());
+        }
+    }
+
+    #[test]
+    fn rand64_derived_outputs() {
+        let mut a = Rand64::seed_from_u64(5);
+        let mut b = a;
+        assert_eq!(a.next_u32(), (b.rand_u64() >> 32) as u32);
+        for _ in 0..1000 {
+            let x = a.rand_f64_range(-2.5, 7.0).unwrap();
+            assert!((-2.5..7.0).contains(&x));
+        }
+        assert_eq!(a.rand_f64_range(1.0, 1.0), Err(uniform::Error::EmptyRange));
+    }
+
+    #[test]
+    fn rand64_drives_distributions() {
+        use crate::bernouilli::Bernoulli;
+        use crate::distribution::Distribution;
+        use crate::uniform::Uniform;
+        use crate::weight_index::WeightIndex;
+
+        let mut rng = Rand64::seed_from_u64(9);
+        let weights = WeightIndex::new([0u32, 3, 0, 1]).unwrap();
+        for i in (&weights).sample_iter(&mut rng).take(100) {
+            assert!(i == 1 || i == 3);
+        }
+        assert!(Bernoulli::new(1.0).unwrap().sample(&mut rng));
+        assert!(Uniform::new(10u64, 20).unwrap().sample(&mut rng) >= 10);
+    }
+
+    #[test]
+    fn from_rng_derives_reproducible_children() {
+        let mut parent = Rand64::seed_from_u64(3);
+        let mut replay = parent;
+        let child = Rand32::from_rng(&mut parent).unwrap();
+        assert_eq!(child, Rand32::from_rng(&mut replay).unwrap());
+        let sibling = Rand32::from_rng(&mut parent).unwrap();
+        assert_ne!(child, sibling);
+    }
+
+    #[test]
+    fn advance_and_backstep() {
+        let mut a = Rand32::seed_from_u64(1);
+        let mut b = a;
+        for _ in 0..1000 {
+            a.rand_u32();
+        }
+        b.advance(1000);
+        assert_eq!(a, b);
+        b.backstep(1000);
+        assert_eq!(b, Rand32::seed_from_u64(1));
+
+        let mut a = Rand64::seed_from_u64(1);
+        let start = a;
+        a.advance(u128::MAX);
+        a.rand_u64();
+        assert_eq!(a, start);
+        a.backstep(7);
+        for _ in 0..7 {
+            a.rand_u64();
+        }
+        assert_eq!(a, start);
+    }
+
+    #[test]
+    fn distance_inverts_advance() {
+        let a = Rand64::seed_from_u64(5);
+        let mut b = a;
+        b.advance(123_456_789_012_345_678_901);
+        assert_eq!(a.distance(&b), Some(123_456_789_012_345_678_901));
+        assert_eq!(b.distance(&a), Some(123_456_789_012_345_678_901u128.wrapping_neg()));
+        assert_eq!(a.distance(&a), Some(0));
+        assert_eq!(a.distance(&Rand64::seed_from_u64(6)), None);
+
+        let a = Rand32::new(3);
+        let mut b = a;
+        b.advance(99);
+        assert_eq!(a.distance(&b), Some(99));
+    }
+
+    #[test]
+    fn distance_rejects_even_increments() {
+        let a = Rand32::from_state((5, 2));
+        assert_eq!(a.distance(&Rand32::from_state((6, 2))), None);
+        assert_eq!(a.distance(&a), None);
+        let a = Rand64::from_state((5, 2));
+        assert_eq!(a.distance(&Rand64::from_state((6, 2))), None);
+    }
+
+    #[test]
+    fn split_uses_another_stream() {
+        let mut parent = Rand64::seed_from_u64(8);
+        let before = parent;
+        let child = parent.split();
+        assert_ne!(child.state().1, parent.state().1);
+        assert_eq!(before.distance(&parent), Some(4));
+        assert_ne!(parent.split(), child);
+
+        let mut parent = Rand32::seed_from_u64(8);
+        let mut child = parent.split();
+        assert_eq!(parent.distance(&child), None);
+        assert_ne!(parent.rand_u32(), child.rand_u32());
+    }
+}
@@ -2,13 +2,19 @@ use std::fmt;
 
 use crate::uniform::{SampleBorrow, SampleUniform, UniformSampler};
 use crate::distribution::Distribution;
-use crate::utils::*;
+use crate::core::RngCore;
 
 #[cfg(feature="serde1")]
-use serde::{Serialize, Deserialize};
+use serde::Serialize;
+#[cfg(any(feature="serde1", feature="candid"))]
+use serde::Deserialize;
 
 #[derive(Debug, Clone, PartialEq)]
-#[cfg_attr(feature="serde1", derive(Serialize, Deserialize))]
+#[cfg_attr(feature="serde1", derive(Serialize))]
+#[cfg_attr(feature="serde1", serde(bound(serialize = "X: Serialize, X::Sampler: Serialize")))]
+#[cfg_attr(any(feature="serde1", feature="candid"), derive(Deserialize))]
+#[cfg_attr(any(feature="serde1", feature="candid"), serde(bound(deserialize = "X: Deserialize<'de> + Clone + Default, X::Sampler: Deserialize<'de>")))]
+#[cfg_attr(any(feature="serde1", feature="candid"), serde(try_from = "WeightIndexRecord<Vec<X>, X, X::Sampler>"))]
 pub struct WeightIndex<X: SampleUniform + PartialOrd> {
     cumulative_weights: Vec<X>,
     total_weight: X,
@@ -42,14 +48,27 @@ impl<X: SampleUniform + PartialOrd> WeightIndex<X> {
         if total_weight == zero {
             return Err(WeightedError::AllWeightsZero);
         }
-        let dist = X::Sampler::new(zero, total_weight.clone()).unwrap();
+        // a float total can overflow to infinity
+        let dist = X::Sampler::new(zero, total_weight.clone())
+            .map_err(|_| WeightedError::InvalidWeight)?;
         Ok(WeightIndex { 
             cumulative_weights: weights,
-             total_weight: total_weight, 
+             total_weight,
              weight_distribution: dist 
         })
     }
 
+    /// The running totals of the weights, excluding the last one, which is
+    /// `total_weight`.
+    pub fn cumulative_weights(&self) -> &[X] {
+        &self.cumulative_weights
+    }
+
+    /// The sum of all weights.
+    pub fn total_weight(&self) -> &X {
+        &self.total_weight
+    }
+
     pub fn update_weights(&mut self, new_weights: &[(usize, &X)]) -> Result<(), WeightedError>
     where X: for<'a> ::core::ops::AddAssign<&'a X>
         + for<'a> ::core::ops::SubAssign<&'a X>
@@ -93,6 +112,10 @@ impl<X: SampleUniform + PartialOrd> WeightIndex<X> {
             if total_weight <= zero {
                 return  Err(WeightedError::AllWeightsZero);
             }
+            // a float total can overflow to infinity; checked before
+            // anything is changed, so an error leaves `self` as it was
+            let dist = X::Sampler::new(zero.clone(), total_weight.clone())
+                .map_err(|_| WeightedError::InvalidWeight)?;
 
             let mut iter = new_weights.iter();
             let mut prev_weight = zero.clone();
@@ -120,15 +143,79 @@ impl<X: SampleUniform + PartialOrd> WeightIndex<X> {
                 core::mem::swap(&mut prev_weight, &mut self.cumulative_weights[i]);
             }
             self.total_weight = total_weight;
-            self.weight_distribution = X::Sampler::new(zero, self.total_weight.clone()).unwrap();
+            self.weight_distribution = dist;
             Ok(())
         }
 }
 
+/// The encoded form of a `WeightIndex`, generic over the field types so
+/// the derives do not need to bound `X::Sampler`. Decoding goes through it
+/// so the running totals are checked and the sampler rebuilt.
+#[cfg(any(feature="serde1", feature="candid"))]
+#[derive(Deserialize)]
+#[cfg_attr(feature = "candid", derive(candid::CandidType))]
+struct WeightIndexRecord<W, T, S> {
+    cumulative_weights: W,
+    total_weight: T,
+    // rebuilt from the total rather than trusted
+    #[allow(dead_code)]
+    weight_distribution: S,
+}
+
+#[cfg(any(feature="serde1", feature="candid"))]
+impl<X> TryFrom<WeightIndexRecord<Vec<X>, X, X::Sampler>> for WeightIndex<X>
+where
+    X: SampleUniform + PartialOrd + Clone + Default,
+{
+    type Error = WeightedError;
+
+    fn try_from(record: WeightIndexRecord<Vec<X>, X, X::Sampler>) -> Result<Self, WeightedError> {
+        let WeightIndexRecord { cumulative_weights, total_weight, .. } = record;
+        let zero = <X as Default>::default();
+        let mut prev = &zero;
+        for w in cumulative_weights.iter().chain(Some(&total_weight)) {
+            if !(w >= prev) {
+                return Err(WeightedError::InvalidWeight);
+            }
+            prev = w;
+        }
+        if total_weight == zero {
+            return Err(WeightedError::AllWeightsZero);
+        }
+        let weight_distribution = X::Sampler::new(zero, total_weight.clone())
+            .map_err(|_| WeightedError::InvalidWeight)?;
+        Ok(WeightIndex {
+            cumulative_weights,
+            total_weight,
+            weight_distribution,
+        })
+    }
+}
+
+#[cfg(feature = "candid")]
+impl<X> candid::CandidType for WeightIndex<X>
+where
+    X: SampleUniform + PartialOrd + candid::CandidType,
+    X::Sampler: candid::CandidType,
+{
+    fn _ty() -> candid::types::Type {
+        WeightIndexRecord::<Vec<X>, X, X::Sampler>::ty()
+    }
+
+    fn idl_serialize<S: candid::types::Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        WeightIndexRecord {
+            cumulative_weights: &self.cumulative_weights,
+            total_weight: &self.total_weight,
+            weight_distribution: &self.weight_distribution,
+        }
+        .idl_serialize(serializer)
+    }
+}
+
 impl<X> Distribution<usize> for WeightIndex<X>
 where X: SampleUniform + PartialOrd 
 {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+    fn sample<R: RngCore + ?Sized>(&self, rng: &mut R) -> usize {
         let choose_weight = self.weight_distribution.sample(rng);
 
         self.cumulative_weights.partition_point(|w| w <= &choose_weight)
@@ -160,4 +247,30 @@ impl fmt::Display for WeightedError {
             WeightedError::TooMany => "Too many weights (hit u32::MAX) in distribution",
         })
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflowing_float_totals_are_errors() {
+        assert_eq!(
+            WeightIndex::new([f64::MAX, f64::MAX]).unwrap_err(),
+            WeightedError::InvalidWeight
+        );
+        assert_eq!(
+            WeightIndex::new([f32::INFINITY]).unwrap_err(),
+            WeightedError::InvalidWeight
+        );
+
+        let mut w = WeightIndex::new([1.0f64, 2.0]).unwrap();
+        let before = w.clone();
+        assert_eq!(
+            w.update_weights(&[(0, &f64::MAX), (1, &f64::MAX)]),
+            Err(WeightedError::InvalidWeight)
+        );
+        assert_eq!(w, before);
+        w.update_weights(&[(1, &3.0)]).unwrap();
+        assert_eq!(w.total_weight(), &4.0);
+    }
+}
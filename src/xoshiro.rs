@@ -0,0 +1,556 @@
+//! The xoshiro and xoroshiro generators of Blackman and Vigna.
+//!
+//! These only use shifts, rotations, xors and small multiplications, so
+//! they stay fast on wasm32 where the `u128` arithmetic of `Rand64` is
+//! costly. They are not cryptographically secure.
+//!
+//! Outputs match the reference C code and the `rand_xoshiro` crate,
+//! including `seed_from_u64`, which expands the seed with SplitMix64 in
+//! both. An all-zero seed would make the generator output zeros forever,
+//! so `from_seed` replaces it with `seed_from_u64(0)`.
+//!
+//! `jump` and `long_jump` advance by a fixed power of two steps, so a
+//! single seed can be cut into non-overlapping streams: call `jump` once
+//! more for each extra stream.
+
+use crate::core::{self as rng_core, Error, RngCore, SeedableRng};
+use crate::stable::{PersistentState, StateError};
+
+/// The xoshiro state transition on 32-bit words.
+#[inline]
+fn xoshiro128_step(s: &mut [u32; 4]) {
+    let t = s[1] << 9;
+    s[2] ^= s[0];
+    s[3] ^= s[1];
+    s[1] ^= s[2];
+    s[0] ^= s[3];
+    s[2] ^= t;
+    s[3] = s[3].rotate_left(11);
+}
+
+/// The xoshiro state transition on 64-bit words.
+#[inline]
+fn xoshiro256_step(s: &mut [u64; 4]) {
+    let t = s[1] << 17;
+    s[2] ^= s[0];
+    s[3] ^= s[1];
+    s[1] ^= s[2];
+    s[0] ^= s[3];
+    s[2] ^= t;
+    s[3] = s[3].rotate_left(45);
+}
+
+/// The xoroshiro128++ state transition.
+#[inline]
+fn xoroshiro128pp_step(s: &mut [u64; 2]) {
+    s[1] ^= s[0];
+    s[0] = s[0].rotate_left(49) ^ s[1] ^ (s[1] << 21);
+    s[1] = s[1].rotate_left(28);
+}
+
+/// The xoroshiro128** state transition.
+#[inline]
+fn xoroshiro128ss_step(s: &mut [u64; 2]) {
+    s[1] ^= s[0];
+    s[0] = s[0].rotate_left(24) ^ s[1] ^ (s[1] << 16);
+    s[1] = s[1].rotate_left(37);
+}
+
+/// Jump by the polynomial `poly`: the new state is the xor of the states
+/// after the steps selected by its bits.
+macro_rules! jump_fn {
+    ($fn:ident, $word:ty) => {
+        fn $fn<const N: usize>(
+            s: &mut [$word; N],
+            poly: &[$word; N],
+            step: fn(&mut [$word; N]),
+        ) {
+            let mut acc = [0; N];
+            for word in poly {
+                for bit in 0..<$word>::BITS {
+                    if word & (1 << bit) != 0 {
+                        for (a, x) in acc.iter_mut().zip(s.iter()) {
+                            *a ^= x;
+                        }
+                    }
+                    step(s);
+                }
+            }
+            *s = acc;
+        }
+    };
+}
+
+jump_fn!(jump_u32, u32);
+jump_fn!(jump_u64, u64);
+
+// the encoded form of every generator below; a derived `Deserialize` would
+// accept the all-zero state that `from_seed` and `read_state` keep out
+#[cfg(any(feature = "serde1", feature = "candid"))]
+#[derive(serde::Deserialize)]
+struct XoshiroRecord<S> {
+    s: S,
+}
+
+macro_rules! xoshiro_impl {
+    (
+        $(#[$meta:meta])*
+        $name:ident: [$word:ty; $n:literal], kind $kind:literal,
+        step $step:ident, jump $jump_fn:ident,
+        output |$s:ident| $out:expr,
+        jump $jump:expr, $jump_steps:literal,
+        long_jump $long_jump:expr, $long_jump_steps:literal $(,)?
+    ) => {
+        $(#[$meta])*
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        #[cfg_attr(feature = "serde1", derive(serde::Serialize))]
+        #[cfg_attr(feature = "candid", derive(candid::CandidType))]
+        pub struct $name {
+            s: [$word; $n],
+        }
+
+        impl $name {
+            #[doc = concat!("Jump ahead by 2^", $jump_steps, " steps.")]
+            pub fn jump(&mut self) {
+                $jump_fn(&mut self.s, &$jump, $step);
+            }
+
+            #[doc = concat!("Jump ahead by 2^", $long_jump_steps, " steps.")]
+            pub fn long_jump(&mut self) {
+                $jump_fn(&mut self.s, &$long_jump, $step);
+            }
+
+            const WORD_LEN: usize = core::mem::size_of::<$word>();
+
+            fn from_le_words(bytes: &[u8]) -> Self {
+                let mut s = [0; $n];
+                for (w, chunk) in s.iter_mut().zip(bytes.chunks_exact(Self::WORD_LEN)) {
+                    *w = <$word>::from_le_bytes(chunk.try_into().unwrap());
+                }
+                Self { s }
+            }
+
+            /// Reject the all-zero state, which only ever outputs zeros.
+            fn check(self) -> Result<Self, StateError> {
+                if self.s == [0; $n] {
+                    return Err(StateError::InvalidState);
+                }
+                Ok(self)
+            }
+
+            #[inline]
+            fn next_word(&mut self) -> $word {
+                let $s = &self.s;
+                let out = $out;
+                $step(&mut self.s);
+                out
+            }
+        }
+
+        impl SeedableRng for $name {
+            /// The state words, little-endian.
+            type Seed = [u8; $n * Self::WORD_LEN];
+
+            fn from_seed(seed: Self::Seed) -> Self {
+                if seed.iter().all(|&b| b == 0) {
+                    return Self::seed_from_u64(0);
+                }
+                Self::from_le_words(&seed)
+            }
+        }
+
+        /// Stored as the state words, little-endian.
+        impl PersistentState for $name {
+            const KIND: u8 = $kind;
+            const STATE_LEN: usize = $n * Self::WORD_LEN;
+
+            fn write_state(&self, out: &mut [u8]) {
+                for (w, chunk) in self.s.iter().zip(out.chunks_exact_mut(Self::WORD_LEN)) {
+                    chunk.copy_from_slice(&w.to_le_bytes());
+                }
+            }
+
+            fn read_state(bytes: &[u8]) -> Result<Self, StateError> {
+                if bytes.len() != Self::STATE_LEN {
+                    return Err(StateError::Truncated);
+                }
+                Self::from_le_words(bytes).check()
+            }
+        }
+
+        /// Decoded as derived, then checked like `read_state`.
+        #[cfg(any(feature = "serde1", feature = "candid"))]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let XoshiroRecord { s } = XoshiroRecord::<[$word; $n]>::deserialize(deserializer)?;
+                Self { s }.check().map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+/// `RngCore` for a generator with 32-bit outputs.
+macro_rules! rng_core_u32 {
+    ($name:ident) => {
+        impl RngCore for $name {
+            #[inline]
+            fn next_u32(&mut self) -> u32 {
+                self.next_word()
+            }
+
+            #[inline]
+            fn next_u64(&mut self) -> u64 {
+                rng_core::next_u64_via_u32(self)
+            }
+
+            #[inline]
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                rng_core::fill_bytes_via_next_u32(self, dest)
+            }
+
+            #[inline]
+            fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+                self.fill_bytes(dest);
+                Ok(())
+            }
+        }
+    };
+}
+
+/// `RngCore` for a generator with 64-bit outputs; `next_u32` takes the
+/// upper or lower half, as `rand_xoshiro` does for that generator.
+macro_rules! rng_core_u64 {
+    ($name:ident, $shift:literal) => {
+        impl RngCore for $name {
+            #[inline]
+            fn next_u32(&mut self) -> u32 {
+                (self.next_word() >> $shift) as u32
+            }
+
+            #[inline]
+            fn next_u64(&mut self) -> u64 {
+                self.next_word()
+            }
+
+            #[inline]
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                rng_core::fill_bytes_via_next_u64(self, dest)
+            }
+
+            #[inline]
+            fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+                self.fill_bytes(dest);
+                Ok(())
+            }
+        }
+    };
+}
+
+const XOSHIRO128_JUMP: [u32; 4] = [0x8764000b, 0xf542d2d3, 0x6fa035c3, 0x77f2db5b];
+const XOSHIRO128_LONG_JUMP: [u32; 4] = [0xb523952e, 0x0b6f099f, 0xccf5a0ef, 0x1c580662];
+const XOSHIRO256_JUMP: [u64; 4] = [
+    0x180ec6d33cfd0aba,
+    0xd5a61266f0c9392c,
+    0xa9582618e03fc9aa,
+    0x39abdc4529b1661c,
+];
+const XOSHIRO256_LONG_JUMP: [u64; 4] = [
+    0x76e15d3efefdcbbf,
+    0xc5004e441c522fb3,
+    0x77710069854ee241,
+    0x39109bb02acbe635,
+];
+
+xoshiro_impl! {
+    /// xoshiro128++: 128 bits of state, 32-bit outputs. The recommended
+    /// 32-bit generator of the family.
+    Xoshiro128PlusPlus: [u32; 4], kind 10,
+    step xoshiro128_step, jump jump_u32,
+    output |s| s[0].wrapping_add(s[3]).rotate_left(7).wrapping_add(s[0]),
+    jump XOSHIRO128_JUMP, 64,
+    long_jump XOSHIRO128_LONG_JUMP, 96,
+}
+rng_core_u32!(Xoshiro128PlusPlus);
+
+xoshiro_impl! {
+    /// xoshiro128**: 128 bits of state, 32-bit outputs.
+    Xoshiro128StarStar: [u32; 4], kind 11,
+    step xoshiro128_step, jump jump_u32,
+    output |s| s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9),
+    jump XOSHIRO128_JUMP, 64,
+    long_jump XOSHIRO128_LONG_JUMP, 96,
+}
+rng_core_u32!(Xoshiro128StarStar);
+
+xoshiro_impl! {
+    /// xoshiro256++: 256 bits of state, 64-bit outputs. The recommended
+    /// general-purpose generator of the family.
+    Xoshiro256PlusPlus: [u64; 4], kind 12,
+    step xoshiro256_step, jump jump_u64,
+    output |s| s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]),
+    jump XOSHIRO256_JUMP, 128,
+    long_jump XOSHIRO256_LONG_JUMP, 192,
+}
+rng_core_u64!(Xoshiro256PlusPlus, 32);
+
+xoshiro_impl! {
+    /// xoshiro256**: 256 bits of state, 64-bit outputs.
+    Xoshiro256StarStar: [u64; 4], kind 13,
+    step xoshiro256_step, jump jump_u64,
+    output |s| s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9),
+    jump XOSHIRO256_JUMP, 128,
+    long_jump XOSHIRO256_LONG_JUMP, 192,
+}
+rng_core_u64!(Xoshiro256StarStar, 32);
+
+xoshiro_impl! {
+    /// xoshiro256+: 256 bits of state, 64-bit outputs. The fastest of the
+    /// family, but the lowest bits are weak; it is meant for generating
+    /// floats from the upper bits.
+    Xoshiro256Plus: [u64; 4], kind 14,
+    step xoshiro256_step, jump jump_u64,
+    output |s| s[0].wrapping_add(s[3]),
+    jump XOSHIRO256_JUMP, 128,
+    long_jump XOSHIRO256_LONG_JUMP, 192,
+}
+rng_core_u64!(Xoshiro256Plus, 32);
+
+xoshiro_impl! {
+    /// xoroshiro128++: 128 bits of state, 64-bit outputs.
+    Xoroshiro128PlusPlus: [u64; 2], kind 15,
+    step xoroshiro128pp_step, jump jump_u64,
+    output |s| s[0].wrapping_add(s[1]).rotate_left(17).wrapping_add(s[0]),
+    jump [0x2bd7a6a6e99c2ddc, 0x0992ccaf6a6fca05], 64,
+    long_jump [0x360fd5f2cf8d5d99, 0x9c6e6877736c46e3], 96,
+}
+rng_core_u64!(Xoroshiro128PlusPlus, 0);
+
+xoshiro_impl! {
+    /// xoroshiro128**: 128 bits of state, 64-bit outputs.
+    Xoroshiro128StarStar: [u64; 2], kind 16,
+    step xoroshiro128ss_step, jump jump_u64,
+    output |s| s[0].wrapping_mul(5).rotate_left(7).wrapping_mul(9),
+    jump [0xdf900294d8f554a5, 0x170865df4b3201fc], 64,
+    long_jump [0xd2a98b26625eee7b, 0xdddf9b1090aa7ac1], 96,
+}
+rng_core_u64!(Xoroshiro128StarStar, 0);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stable::{decode_state, encode_state};
+
+    fn seed_words<G: SeedableRng>(word_size: usize) -> G {
+        let mut seed = G::Seed::default();
+        for (i, chunk) in seed.as_mut().chunks_mut(word_size).enumerate() {
+            chunk[0] = i as u8 + 1;
+        }
+        G::from_seed(seed)
+    }
+
+    fn take<G: RngCore>(rng: &mut G, n: usize, wide: bool) -> Vec<u64> {
+        (0..n)
+            .map(|_| {
+                if wide {
+                    rng.next_u64()
+                } else {
+                    rng.next_u32() as u64
+                }
+            })
+            .collect()
+    }
+
+    /// Check `G` seeded with the words 1, 2, ... against `reference`, and
+    /// the first two outputs after `jump`, after `long_jump` and from
+    /// `seed_from_u64(0)`.
+    fn check<G>(
+        word_size: usize,
+        reference: [u64; 6],
+        jump: [u64; 2],
+        long_jump: [u64; 2],
+        from_u64: [u64; 2],
+        do_jump: fn(&mut G),
+        do_long_jump: fn(&mut G),
+    ) where
+        G: SeedableRng + RngCore + Clone,
+    {
+        let wide = word_size == 8;
+        let rng: G = seed_words(word_size);
+        assert_eq!(take(&mut rng.clone(), 6, wide), reference);
+
+        let mut jumped = rng.clone();
+        do_jump(&mut jumped);
+        assert_eq!(take(&mut jumped, 2, wide), jump);
+
+        let mut jumped = rng;
+        do_long_jump(&mut jumped);
+        assert_eq!(take(&mut jumped, 2, wide), long_jump);
+
+        assert_eq!(take(&mut G::seed_from_u64(0), 2, wide), from_u64);
+        assert_eq!(
+            take(&mut G::from_seed(G::Seed::default()), 2, wide),
+            from_u64
+        );
+    }
+
+    // The first six outputs of each generator are from the reference C
+    // code; the rest were produced with rand_xoshiro 0.6.
+
+    #[test]
+    fn xoshiro128_reference() {
+        check::<Xoshiro128PlusPlus>(
+            4,
+            [641, 1573767, 3222811527, 3517856514, 836907274, 4247214768],
+            [3129740764, 111290574],
+            [2580293941, 2135890358],
+            [1179900579, 1938959192],
+            Xoshiro128PlusPlus::jump,
+            Xoshiro128PlusPlus::long_jump,
+        );
+        check::<Xoshiro128StarStar>(
+            4,
+            [11520, 0, 5927040, 70819200, 2031721883, 1637235492],
+            [1194304935, 745561276],
+            [4148901660, 60341234],
+            [3737715805, 2584255861],
+            Xoshiro128StarStar::jump,
+            Xoshiro128StarStar::long_jump,
+        );
+    }
+
+    #[test]
+    fn xoshiro256_reference() {
+        check::<Xoshiro256PlusPlus>(
+            8,
+            [
+                41943041,
+                58720359,
+                3588806011781223,
+                3591011842654386,
+                9228616714210784205,
+                9973669472204895162,
+            ],
+            [17043750140134683703, 2364973248208838314],
+            [13097851138432240629, 5869259491745178931],
+            [5987356902031041503, 7051070477665621255],
+            Xoshiro256PlusPlus::jump,
+            Xoshiro256PlusPlus::long_jump,
+        );
+        check::<Xoshiro256StarStar>(
+            8,
+            [
+                11520,
+                0,
+                1509978240,
+                1215971899390074240,
+                1216172134540287360,
+                607988272756665600,
+            ],
+            [13534147089533256664, 7126240192422241655],
+            [5942309088398569549, 15625447729937358436],
+            [11091344671253066420, 13793997310169335082],
+            Xoshiro256StarStar::jump,
+            Xoshiro256StarStar::long_jump,
+        );
+        check::<Xoshiro256Plus>(
+            8,
+            [
+                5,
+                211106232532999,
+                211106635186183,
+                9223759065350669058,
+                9250833439874351877,
+                13862484359527728515,
+            ],
+            [1153146630064993313, 12314415065245919719],
+            [4237864540600467441, 12093458965634073548],
+            [15757075719729598363, 3555206913761248309],
+            Xoshiro256Plus::jump,
+            Xoshiro256Plus::long_jump,
+        );
+    }
+
+    #[test]
+    fn xoroshiro128_reference() {
+        check::<Xoroshiro128PlusPlus>(
+            8,
+            [
+                393217,
+                669327710093319,
+                1732421326133921491,
+                11394790081659126983,
+                9555452776773192676,
+                3586421180005889563,
+            ],
+            [6995778298204176446, 17606341508358386873],
+            [13476878559037916028, 4599739792799904096],
+            [8027914721839836897, 13805533416164201645],
+            Xoroshiro128PlusPlus::jump,
+            Xoroshiro128PlusPlus::long_jump,
+        );
+        check::<Xoroshiro128StarStar>(
+            8,
+            [
+                5760,
+                97769243520,
+                9706862127477703552,
+                9223447511460779954,
+                8358291023205304566,
+                15695619998649302768,
+            ],
+            [2464231652016875657, 11602794600843324846],
+            [1154914562721061336, 6059381922964790418],
+            [16053376993090331485, 7868822567099391496],
+            Xoroshiro128StarStar::jump,
+            Xoroshiro128StarStar::long_jump,
+        );
+    }
+
+    #[test]
+    fn narrow_outputs_and_states() {
+        // the 64-bit xoshiro generators give the upper half as u32, the
+        // xoroshiro generators the lower half, as in rand_xoshiro
+        let mut a = Xoshiro256PlusPlus::seed_from_u64(7);
+        let mut b = a;
+        assert_eq!(a.next_u32(), (b.next_u64() >> 32) as u32);
+        let mut a = Xoroshiro128PlusPlus::seed_from_u64(7);
+        let mut b = a;
+        assert_eq!(a.next_u32(), b.next_u64() as u32);
+
+        let mut rng = Xoshiro128StarStar::seed_from_u64(3);
+        rng.next_u32();
+        let mut restored: Xoshiro128StarStar = decode_state(&encode_state(&rng)).unwrap();
+        assert_eq!(restored, rng);
+        assert_eq!(restored.next_u64(), rng.next_u64());
+        assert_eq!(
+            Xoshiro256Plus::read_state(&[0; 32]),
+            Err(StateError::InvalidState)
+        );
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn serde_rejects_zero_state() {
+        let rng = Xoroshiro128PlusPlus::seed_from_u64(1);
+        let json = serde_json::to_string(&rng).unwrap();
+        assert_eq!(serde_json::from_str::<Xoroshiro128PlusPlus>(&json).unwrap(), rng);
+        assert!(serde_json::from_str::<Xoroshiro128PlusPlus>(r#"{"s":[0,0]}"#).is_err());
+        assert!(serde_json::from_str::<Xoroshiro128PlusPlus>(r#"{"s":[0,1]}"#).is_ok());
+
+        let bytes = bincode::serialize(&[0u32; 4]).unwrap();
+        assert!(bincode::deserialize::<Xoshiro128PlusPlus>(&bytes).is_err());
+    }
+
+    #[cfg(feature = "candid")]
+    #[test]
+    fn candid_rejects_zero_state() {
+        #[derive(candid::CandidType)]
+        struct Record {
+            s: [u64; 4],
+        }
+
+        let decode = |s| candid::decode_one::<Xoshiro256StarStar>(&candid::encode_one(Record { s }).unwrap());
+        assert!(decode([0, 0, 0, 1]).is_ok());
+        assert!(decode([0; 4]).is_err());
+    }
+}